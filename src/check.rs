@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use crate::config;
+use crate::site::{find_url_collisions, load_discoverable, parse_site_url, ContentRenderer, Page, Post, SiteContent};
+use crate::util::describe_error_chain;
+
+/// The templates that every site must have. The others, such as `404.html`, are optional.
+const REQUIRED_TEMPLATES: [&str; 5] = ["latest_post.html", "post.html", "page.html", "tag.html", "archive.html"];
+
+/// A problem found while checking a site.
+pub struct Problem {
+	/// Where the problem is, e.g. the path of a config or content file.
+	pub source: String,
+	pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {}", self.source, self.message)
+	}
+}
+
+struct Checker<'a> {
+	site_root: &'a Path,
+	problems: Vec<Problem>,
+	/// The content files whose content has already been checked, as the same file can be listed more than once.
+	checked_files: HashSet<PathBuf>,
+}
+
+impl<'a> Checker<'a> {
+	/// Describes a path relative to the site root, which is much easier to read than the full path.
+	fn describe_path(&self, path: &Path) -> String {
+		path.strip_prefix(self.site_root).unwrap_or(path).display().to_string()
+	}
+
+	fn add(&mut self, source: impl Into<String>, message: impl Into<String>) {
+		self.problems.push(Problem { source: source.into(), message: message.into() });
+	}
+
+	fn add_for_path(&mut self, path: &Path, message: impl Into<String>) {
+		let source = self.describe_path(path);
+		self.add(source, message);
+	}
+
+	fn add_error(&mut self, source: impl Into<String>, error: &dyn std::error::Error) {
+		self.add(source, describe_error_chain(error));
+	}
+
+	fn check_templates(&mut self, server_config: &config::Server) -> Option<tera::Tera> {
+		let source = self.describe_path(&server_config.templates_path);
+		match SiteContent::load_templates(server_config) {
+			Err(err) => {
+				self.add_error(source, &err);
+				None
+			}
+			Ok(template_renderer) => {
+				for name in REQUIRED_TEMPLATES {
+					if !template_renderer.get_template_names().any(|template| template == name) {
+						self.add(source.clone(), format!("Required template {name} is missing"));
+					}
+				}
+				Some(template_renderer)
+			}
+		}
+	}
+
+	/// Renders a single content file, checking its code blocks along the way. Returns false if it could not be
+	/// rendered, in which case the problem has already been added.
+	fn check_content_file(
+		&mut self,
+		file_path: &PathBuf,
+		config_name: &str,
+		content_renderer: &ContentRenderer,
+	) -> bool {
+		if !file_path.is_file() {
+			self.add(config_name, format!("Content file {} does not exist", self.describe_path(file_path)));
+			return false;
+		}
+		match content_renderer.render(file_path, None) {
+			Err(err) => {
+				self.add_error(self.describe_path(file_path), &err);
+				false
+			}
+			Ok(rendered) => {
+				if !self.checked_files.insert(file_path.clone()) {
+					return true;
+				}
+				for language in rendered.output.unknown_languages.iter().unique() {
+					self.add_for_path(file_path, format!("Code block language \"{language}\" has no matching syntax"));
+				}
+				for warning in rendered.output.warnings.iter() {
+					self.add_for_path(file_path, warning.clone());
+				}
+				true
+			}
+		}
+	}
+
+	/// Renders every post and page, as well as all of the other pages of the site, through the templates.
+	fn check_rendering(&mut self, content: &SiteContent) {
+		let templates_path = self.describe_path(&content.server_config.templates_path);
+		for page in content.pages.iter() {
+			if let Err(err) = content.render_page(page) {
+				self.add_error(self.describe_path(&page.file_path), &err);
+			}
+		}
+		for post in content.posts.iter() {
+			if let Err(err) = content.render_post(post) {
+				self.add_error(self.describe_path(&post.file_path), &err);
+			}
+		}
+		if let Err(err) = content.render_latest_post() {
+			self.add_error(templates_path.clone(), &err);
+		}
+		if let Err(err) = content.render_posts_archive(1) {
+			self.add_error(templates_path.clone(), &err);
+		}
+		for tag in content.post_tag_mappings.tags().sorted() {
+			if let Err(err) = content.render_posts_by_tag(tag, 1) {
+				self.add_error(templates_path.clone(), &err);
+			}
+		}
+		if let Err(err) = content.render_not_found() {
+			self.add_error(templates_path.clone(), &err);
+		}
+		if let Err(err) = content.render_error(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR, None) {
+			self.add_error(templates_path, &err);
+		}
+	}
+}
+
+/// Checks the site with the given config files, reporting every problem found with it rather than stopping at the
+/// first one. Checking stops early only if the configs themselves cannot be loaded.
+pub fn check_site(
+	site_root: &Path,
+	server_config_path: &PathBuf,
+	pages_config_path: &PathBuf,
+	posts_config_path: &PathBuf,
+) -> Vec<Problem> {
+	let mut checker = Checker { site_root, problems: Vec::new(), checked_files: HashSet::new() };
+
+	let server_config = match config::load_server(server_config_path, &site_root.to_path_buf()) {
+		Err(err) => {
+			checker.add_error(checker.describe_path(server_config_path), &err);
+			return checker.problems;
+		}
+		Ok(server_config) => server_config,
+	};
+	let (pages_config, posts_config) = match config::load_content(pages_config_path, posts_config_path, &server_config)
+	{
+		Err(err) => {
+			checker.add_error("pages.yml/posts.yml", &err);
+			return checker.problems;
+		}
+		Ok(configs) => configs,
+	};
+	let content_renderer = match ContentRenderer::new(&server_config) {
+		Err(err) => {
+			checker.add_error(checker.describe_path(server_config_path), &err);
+			return checker.problems;
+		}
+		Ok(content_renderer) => Arc::new(content_renderer),
+	};
+
+	let template_renderer = checker.check_templates(&server_config);
+
+	let is_site_url_valid = match parse_site_url(&posts_config.rss) {
+		Err(err) => {
+			checker.add_error("posts.yml", &err);
+			false
+		}
+		Ok(_) => true,
+	};
+
+	// discovered files without a title are skipped rather than being problems, same as when the site is served
+	let mut skipped_count = 0;
+	let mut pages = Vec::new();
+	for page_config in pages_config.pages.iter() {
+		if checker.check_content_file(&page_config.file_path, "pages.yml", &content_renderer) {
+			let (file_path, discovered) = (&page_config.file_path, page_config.discovered);
+			match load_discoverable(file_path, discovered, || {
+				Page::try_from(page_config.clone(), &content_renderer, None)
+			}) {
+				Err(err) => checker.add_error(checker.describe_path(file_path), &err),
+				Ok(None) => skipped_count += 1,
+				Ok(Some(page)) => pages.push(page),
+			}
+		}
+	}
+	let mut posts = Vec::new();
+	for post_config in posts_config.posts.iter() {
+		if checker.check_content_file(&post_config.file_path, "posts.yml", &content_renderer) {
+			let (file_path, discovered) = (&post_config.file_path, post_config.discovered);
+			match load_discoverable(file_path, discovered, || {
+				Post::try_from(post_config.clone(), &content_renderer, None)
+			}) {
+				Err(err) => checker.add_error(checker.describe_path(file_path), &err),
+				Ok(None) => skipped_count += 1,
+				Ok(Some(post)) => posts.push(post),
+			}
+		}
+	}
+
+	let url_collisions = find_url_collisions(&pages, &posts);
+	for error in url_collisions.iter() {
+		checker.add_error("pages.yml/posts.yml", error);
+	}
+
+	// rendering through the templates and checking links is only possible once all of the content could be loaded,
+	// and the absolute urls in feeds and the sitemap can only be built from a valid site url
+	let is_content_loaded = pages.len() + posts.len() + skipped_count
+		== pages_config.pages.len() + posts_config.posts.len()
+		&& url_collisions.is_empty()
+		&& is_site_url_valid;
+	if let (Some(template_renderer), true) = (template_renderer, is_content_loaded) {
+		let server_config = Arc::new(server_config);
+		let template_renderer = Arc::new(template_renderer);
+		match SiteContent::new(pages_config, posts_config, server_config, content_renderer, template_renderer, None) {
+			Err(err) => checker.add_error("pages.yml/posts.yml", &err),
+			Ok(content) => {
+				checker.check_rendering(&content);
+				for link in content.find_broken_links() {
+					checker.add_for_path(&link.file_path, format!("Link to {} is broken", link.url));
+				}
+			}
+		}
+	}
+
+	checker.problems
+}
@@ -1,8 +1,11 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 
-use crate::util::drop_trailing_slash;
+use crate::formats::has_content_format;
+use crate::util::{find_files, url_from_page_file_path};
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Server {
@@ -11,8 +14,55 @@ pub struct Server {
 	pub static_files_path: PathBuf,
 	pub templates_path: PathBuf,
 	pub syntaxes_path: Option<PathBuf>,
+	pub syntax_theme: Option<String>,
+	#[serde(default)]
+	pub syntax_inline_styles: bool,
+	#[serde(default)]
+	pub syntax_aliases: HashMap<String, String>,
+	#[serde(default)]
+	pub detect_code_languages: bool,
 	pub pages_path: PathBuf,
 	pub posts_path: PathBuf,
+	#[serde(default)]
+	pub content_formats: HashMap<String, String>,
+	#[serde(default)]
+	pub discover_pages: bool,
+	#[serde(default)]
+	pub discover_posts: bool,
+	pub preview_key: Option<String>,
+	pub posts_per_page: Option<NonZeroUsize>,
+	pub homepage_posts: Option<usize>,
+	pub summary_paragraphs: Option<usize>,
+	#[serde(default)]
+	pub heading_anchors: bool,
+	#[serde(default)]
+	pub feeds: Feeds,
+	pub timezone: Option<chrono_tz::Tz>,
+	#[serde(default)]
+	pub generate_robots_txt: bool,
+	#[serde(default)]
+	pub dev_mode: bool,
+	pub status_key: Option<String>,
+	#[serde(default)]
+	pub warn_broken_links: bool,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct Feeds {
+	pub atom_path: String,
+	pub json_feed_path: String,
+	pub full_content: bool,
+}
+
+impl Default for Feeds {
+	fn default() -> Self {
+		Feeds {
+			atom_path: String::from("/atom.xml"), //
+			json_feed_path: String::from("/feed.json"),
+			full_content: true,
+		}
+	}
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -26,9 +76,15 @@ pub struct Rss {
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Page {
 	pub file_path: PathBuf,
-	pub title: String,
-	pub url: String,
+	pub title: Option<String>,
+	pub url: Option<String>,
 	pub alternate_urls: Option<Vec<String>>,
+	/// The url derived from the page's file path, used only if no url is otherwise specified for this page.
+	#[serde(skip)]
+	pub default_url: Option<String>,
+	/// Whether this page was discovered under `pages_path` rather than being listed in `pages.yml`.
+	#[serde(skip)]
+	pub discovered: bool,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -39,12 +95,31 @@ pub struct Pages {
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Post {
 	pub file_path: PathBuf,
-	pub title: String,
-	#[serde(deserialize_with = "crate::util::deserialize_string_to_naivedatetime")]
-	pub date: chrono::NaiveDateTime,
-	pub slug: String,
+	pub title: Option<String>,
+	#[serde(default, deserialize_with = "crate::util::deserialize_optional_string_to_naivedatetime")]
+	pub date: Option<chrono::NaiveDateTime>,
+	pub slug: Option<String>,
+	pub alternate_urls: Option<Vec<String>>,
+	pub tags: Option<Vec<String>>,
+	pub draft: Option<bool>,
+	/// Whether this post was discovered under `posts_path` rather than being listed in `posts.yml`.
+	#[serde(skip)]
+	pub discovered: bool,
+}
+
+/// Optional metadata found at the top of a post or page content file. Any of these values are only used when the
+/// matching value has not been set in the post or page's entry in `posts.yml` or `pages.yml`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct FrontMatter {
+	pub title: Option<String>,
+	#[serde(default, deserialize_with = "crate::util::deserialize_optional_string_to_naivedatetime")]
+	pub date: Option<chrono::NaiveDateTime>,
+	pub slug: Option<String>,
+	pub url: Option<String>,
 	pub alternate_urls: Option<Vec<String>>,
 	pub tags: Option<Vec<String>>,
+	pub draft: Option<bool>,
+	pub description: Option<String>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -74,6 +149,33 @@ where
 	}
 }
 
+impl Server {
+	/// Returns the path of the syntax theme file, if the syntax theme is a `.tmTheme` file rather than the name of a
+	/// built-in theme.
+	pub fn syntax_theme_file(&self) -> Option<PathBuf> {
+		self.syntax_theme.as_ref().filter(|theme| theme.ends_with(".tmTheme")).map(PathBuf::from)
+	}
+
+	/// Restores the settings which are only used when the server is started from the config of the currently running
+	/// server, returning the names of any of these settings which had been changed.
+	pub fn keep_startup_settings(&mut self, running: &Server) -> Vec<&'static str> {
+		fn keep<T: PartialEq + Clone>(name: &'static str, value: &mut T, running: &T, changed: &mut Vec<&'static str>) {
+			if value != running {
+				changed.push(name);
+				*value = running.clone();
+			}
+		}
+		let mut changed = Vec::new();
+		keep("bind_addr", &mut self.bind_addr, &running.bind_addr, &mut changed);
+		keep("bind_port", &mut self.bind_port, &running.bind_port, &mut changed);
+		keep("static_files_path", &mut self.static_files_path, &running.static_files_path, &mut changed);
+		keep("dev_mode", &mut self.dev_mode, &running.dev_mode, &mut changed);
+		keep("feeds.atom_path", &mut self.feeds.atom_path, &running.feeds.atom_path, &mut changed);
+		keep("feeds.json_feed_path", &mut self.feeds.json_feed_path, &running.feeds.json_feed_path, &mut changed);
+		changed
+	}
+}
+
 pub fn load_server(path: &PathBuf, site_root: &PathBuf) -> Result<Server, ConfigError> {
 	log::info!("Loading server config from {:?}", path);
 	let mut server_config: Server = load_config(path)?;
@@ -84,9 +186,39 @@ pub fn load_server(path: &PathBuf, site_root: &PathBuf) -> Result<Server, Config
 	if let Some(syntaxes_path) = &server_config.syntaxes_path {
 		server_config.syntaxes_path = Some([site_root, syntaxes_path].iter().collect());
 	}
+	// the syntax theme is either the name of one of syntect's built-in themes, or the path of a theme file
+	if let Some(theme_path) = server_config.syntax_theme_file() {
+		server_config.syntax_theme = Some(site_root.join(theme_path).to_string_lossy().to_string());
+	}
 	Ok(server_config)
 }
 
+/// Returns all of the content files found under the given path which are not already in `known_file_paths`. If
+/// `discover` is false, these files are only logged as a warning, as they will not be published. Only files with an
+/// extension that a content format is used for count as content files, so that images and other files can be kept
+/// alongside the content.
+fn discover_content_files(
+	path: &Path,
+	known_file_paths: &[&PathBuf],
+	discover: bool,
+	config_name: &str,
+	server_config: &Server,
+) -> Result<Vec<PathBuf>, ConfigError> {
+	let file_paths = find_files(path).map_err(|e| ConfigError::IOError(path.to_path_buf(), e))?;
+	let mut discovered = Vec::new();
+	for file_path in file_paths.into_iter().filter(|file_path| !known_file_paths.contains(&file_path)) {
+		if !has_content_format(server_config, &file_path) {
+			log::debug!("Ignoring file {:?} as it has no content format", file_path);
+		} else if discover {
+			log::info!("Discovered content file {:?} which is not listed in {}", file_path, config_name);
+			discovered.push(file_path);
+		} else {
+			log::warn!("Content file {:?} is not listed in {} and will not be published", file_path, config_name);
+		}
+	}
+	Ok(discovered)
+}
+
 pub fn load_content(
 	pages_path: &PathBuf,
 	posts_path: &PathBuf,
@@ -96,22 +228,74 @@ pub fn load_content(
 	let mut pages: Pages = load_config(pages_path)?;
 	for page in pages.pages.iter_mut() {
 		page.file_path = [&server_config.pages_path, &page.file_path].iter().collect();
-		drop_trailing_slash(&mut page.url);
-		if let Some(alternate_urls) = &mut page.alternate_urls {
-			for alternate_url in alternate_urls.iter_mut() {
-				drop_trailing_slash(alternate_url);
-			}
-		}
 	}
+	let known_file_paths: Vec<&PathBuf> = pages.pages.iter().map(|page| &page.file_path).collect();
+	let discovered = discover_content_files(
+		&server_config.pages_path,
+		&known_file_paths,
+		server_config.discover_pages,
+		"pages.yml",
+		server_config,
+	)?;
+	pages.pages.extend(discovered.into_iter().map(|file_path| Page {
+		file_path,
+		title: None,
+		url: None,
+		alternate_urls: None,
+		default_url: None,
+		discovered: true,
+	}));
+	for page in pages.pages.iter_mut() {
+		page.default_url = url_from_page_file_path(&page.file_path, &server_config.pages_path);
+	}
+
 	log::info!("Loading posts config from {:?}", posts_path);
 	let mut posts: Posts = load_config(posts_path)?;
 	for post in posts.posts.iter_mut() {
 		post.file_path = [&server_config.posts_path, &post.file_path].iter().collect();
-		if let Some(alternate_urls) = &mut post.alternate_urls {
-			for alternate_url in alternate_urls.iter_mut() {
-				drop_trailing_slash(alternate_url);
-			}
-		}
 	}
+	let known_file_paths: Vec<&PathBuf> = posts.posts.iter().map(|post| &post.file_path).collect();
+	let discovered = discover_content_files(
+		&server_config.posts_path,
+		&known_file_paths,
+		server_config.discover_posts,
+		"posts.yml",
+		server_config,
+	)?;
+	posts.posts.extend(discovered.into_iter().map(|file_path| Post {
+		file_path,
+		title: None,
+		date: None,
+		slug: None,
+		alternate_urls: None,
+		tags: None,
+		draft: None,
+		discovered: true,
+	}));
+
 	Ok((pages, posts))
 }
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+
+	/// Returns the YAML of a server config with only the required settings, followed by `extra_config`.
+	pub fn server_config_yaml(extra_config: &str) -> String {
+		format!(
+			"bind_addr: 127.0.0.1\nbind_port: 8080\nstatic_files_path: static\ntemplates_path: templates\npages_path: pages\nposts_path: posts\n{extra_config}"
+		)
+	}
+
+	/// Returns a server config with only the required settings, along with any given in `extra_config`.
+	pub fn server_config(extra_config: &str) -> Server {
+		serde_yaml::from_str(&server_config_yaml(extra_config)).unwrap()
+	}
+
+	#[test]
+	fn posts_per_page_must_not_be_zero() {
+		assert_eq!(server_config("posts_per_page: 5").posts_per_page, NonZeroUsize::new(5));
+		assert_eq!(server_config("").posts_per_page, None);
+		assert!(serde_yaml::from_str::<Server>(&server_config_yaml("posts_per_page: 0")).is_err());
+	}
+}
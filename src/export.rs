@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+
+use crate::feeds::FeedFormat;
+use crate::site;
+use crate::util::{copy_dir_all, decode_path};
+
+const FEED_FORMATS: [FeedFormat; 3] = [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json];
+
+/// File extensions of authored urls that are written out verbatim as an HTML file, rather than an `index.html` file.
+const HTML_EXTENSIONS: [&str; 2] = ["html", "htm"];
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+	#[error("Export I/O error with path {0}")]
+	IOError(PathBuf, #[source] std::io::Error),
+
+	#[error("Site rendering error")]
+	SiteError(#[from] site::SiteError),
+
+	#[error("Url {0} cannot be exported, as it has a \".\" or \"..\" segment, or a segment with an encoded \"/\"")]
+	UnsafeUrlError(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+	/// HTML content at a url which does not name a file, e.g. an archive or tag page, or a post.
+	Html,
+	/// HTML content at a url chosen by the site author which may name an HTML file, i.e. that of a page or an
+	/// alternate url.
+	AuthoredHtml,
+	/// Anything that is not HTML, such as feeds.
+	File,
+}
+
+/// Returns the file path that the content served at the given url should be written to. HTML content is written out
+/// as an `index.html` file in a directory named after the url so that dumb file hosts will serve it at the same url
+/// that PBE would. The exception is an authored url that already ends in an HTML file name (e.g. an alternate url such
+/// as `/old.html`), which is written to a file whose path matches the url exactly, the same as everything else.
+/// Urls that could lead outside of the output path are rejected.
+fn output_path_for_url(output_path: &Path, url: &str, output: Output) -> Result<PathBuf, ExportError> {
+	let mut path = output_path.to_path_buf();
+	let mut name = String::new();
+	for segment in url.split('/').filter(|segment| !segment.is_empty()) {
+		// file hosts decode urls before looking up the file for them, so the file path needs to be decoded as well
+		name = decode_path(segment);
+		if name == "." || name == ".." || name.contains('/') {
+			return Err(ExportError::UnsafeUrlError(url.to_string()));
+		}
+		path.push(&name);
+	}
+	let is_html_file = Path::new(&name)
+		.extension()
+		.is_some_and(|extension| HTML_EXTENSIONS.iter().any(|html| extension.eq_ignore_ascii_case(html)));
+	if output == Output::Html || (output == Output::AuthoredHtml && !is_html_file) {
+		path.push("index.html");
+	}
+	Ok(path)
+}
+
+fn write_output(output_path: &Path, url: &str, output: Output, contents: &str) -> Result<(), ExportError> {
+	let path = output_path_for_url(output_path, url, output)?;
+	log::debug!("Writing {} to {:?}", url, path);
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent).map_err(|e| ExportError::IOError(parent.to_path_buf(), e))?;
+	}
+	std::fs::write(&path, contents).map_err(|e| ExportError::IOError(path.clone(), e))
+}
+
+/// Writes out every page of a paginated list of posts, where `render` returns `None` for the first page past the end.
+fn write_paginated<F>(output_path: &Path, base_url: &str, render: F) -> Result<(), ExportError>
+where
+	F: Fn(usize) -> Result<Option<String>, site::SiteError>,
+{
+	let mut page = 1;
+	while let Some(rendered) = render(page)? {
+		write_output(output_path, &site::Paginator::page_url(base_url, page), Output::Html, &rendered)?;
+		page += 1;
+	}
+	Ok(())
+}
+
+fn redirect_stub(url: &str) -> String {
+	let url = tera::escape_html(url);
+	format!(
+		"<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n\t<meta charset=\"UTF-8\">\n\t<meta http-equiv=\"refresh\" content=\"0; url={url}\">\n\t<link rel=\"canonical\" href=\"{url}\">\n</head>\n<body>\n\t<a href=\"{url}\">{url}</a>\n</body>\n</html>\n"
+	)
+}
+
+/// Renders everything that [`site::SiteService`] would serve out to files under `output_path`, followed by a copy of
+/// the static files. Static files are copied last, as the live server also gives them priority over site content.
+pub fn export_site(site_service: &site::SiteService, output_path: &Path) -> Result<(), ExportError> {
+	let content = site_service.content.load_full();
+
+	log::info!("Exporting latest post, archive and feeds");
+	write_output(output_path, "/", Output::Html, &content.render_latest_post()?)?;
+	write_paginated(output_path, "/archive", |page| content.render_posts_archive(page))?;
+	for format in FEED_FORMATS {
+		write_output(output_path, &content.feed_path(format, None), Output::File, &content.render_feed(format, None))?;
+	}
+
+	// only published posts are exported, as well as only the tags that have at least one published post. since
+	// there is no way for unpublished posts to automatically appear later on in the exported files, the site will
+	// need to be exported again once any scheduled posts are due to be published
+	log::info!("Exporting tags");
+	for tag in content.post_tag_mappings.tags() {
+		if !content.get_posts_with_tag_ordered_by_date(tag).is_empty() {
+			write_paginated(output_path, &site::tag_url(tag), |page| content.render_posts_by_tag(tag, page))?;
+			for format in FEED_FORMATS {
+				let feed_path = content.feed_path(format, Some(tag));
+				write_output(output_path, &feed_path, Output::File, &content.render_feed(format, Some(tag)))?;
+			}
+		}
+	}
+
+	log::info!("Exporting sitemap");
+	write_output(output_path, site::SITEMAP_PATH, Output::File, &content.render_sitemap())?;
+	if content.server_config.generate_robots_txt {
+		// this is overwritten when static files are copied if there is a static robots.txt, same as when serving.
+		// the same goes for syntax.css below
+		write_output(output_path, site::ROBOTS_TXT_PATH, Output::File, &content.render_robots_txt())?;
+	}
+	if let Some(syntax_css) = content.content_renderer.syntax_css() {
+		write_output(output_path, site::SYNTAX_CSS_PATH, Output::File, syntax_css)?;
+	}
+
+	// most static file hosts will serve this for any url that does not exist
+	if let Some(not_found) = content.render_not_found()? {
+		write_output(output_path, "/404.html", Output::File, &not_found)?;
+	}
+
+	let posts = content.get_posts_ordered_by_date();
+	log::info!("Exporting {} posts", posts.len());
+	for post in posts {
+		write_output(output_path, &post.url, Output::Html, &content.render_post(post)?)?;
+	}
+
+	log::info!("Exporting {} pages", content.pages.len());
+	for page in content.pages.iter() {
+		write_output(output_path, &page.url, Output::AuthoredHtml, &content.render_page(page)?)?;
+	}
+
+	log::info!("Exporting alternate url redirects");
+	for (alternate_url, url) in content.alternate_url_mappings.iter() {
+		if content.get_content_at(alternate_url, false).is_some() {
+			write_output(output_path, alternate_url, Output::AuthoredHtml, &redirect_stub(url))?;
+		}
+	}
+
+	let static_files_path = &content.server_config.static_files_path;
+	log::info!("Copying static files from {:?}", static_files_path);
+	copy_dir_all(static_files_path, output_path).map_err(|e| ExportError::IOError(static_files_path.clone(), e))?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn path_for(url: &str, output: Output) -> PathBuf {
+		output_path_for_url(Path::new("/out"), url, output).unwrap()
+	}
+
+	#[test]
+	fn html_is_written_to_index_files() {
+		assert_eq!(path_for("/", Output::Html), Path::new("/out/index.html"));
+		assert_eq!(path_for("/archive/page/2", Output::Html), Path::new("/out/archive/page/2/index.html"));
+		assert_eq!(path_for("/about", Output::AuthoredHtml), Path::new("/out/about/index.html"));
+	}
+
+	#[test]
+	fn authored_urls_with_extensions_are_written_verbatim() {
+		assert_eq!(path_for("/old.html", Output::AuthoredHtml), Path::new("/out/old.html"));
+		assert_eq!(path_for("/blog/OLD.HTM", Output::AuthoredHtml), Path::new("/out/blog/OLD.HTM"));
+		// only html files can be served as html by file hosts, so anything else still needs to be a directory
+		assert_eq!(path_for("/blog/post.php", Output::AuthoredHtml), Path::new("/out/blog/post.php/index.html"));
+		// post urls never name a file, even when their slug has dots in it
+		assert_eq!(
+			path_for("/2023/06/30/pbe-0.3.1-released", Output::Html),
+			Path::new("/out/2023/06/30/pbe-0.3.1-released/index.html")
+		);
+		assert_eq!(path_for("/2023/06/30/old.html", Output::Html), Path::new("/out/2023/06/30/old.html/index.html"));
+		// tags can contain dots, and their pages still need to be directories to hold their feeds
+		assert_eq!(path_for("/tag/node.js", Output::Html), Path::new("/out/tag/node.js/index.html"));
+	}
+
+	#[test]
+	fn other_files_are_written_verbatim() {
+		assert_eq!(path_for("/rss", Output::File), Path::new("/out/rss"));
+		assert_eq!(path_for("/tag/rust/atom.xml", Output::File), Path::new("/out/tag/rust/atom.xml"));
+	}
+
+	#[test]
+	fn percent_encoded_urls_are_decoded() {
+		assert_eq!(path_for("/tag/c%23", Output::Html), Path::new("/out/tag/c#/index.html"));
+		assert_eq!(path_for("/tag/c%23/rss", Output::File), Path::new("/out/tag/c#/rss"));
+	}
+
+	#[test]
+	fn urls_leading_outside_of_the_output_path_are_rejected() {
+		for url in ["/../../escape", "/a/./b", "/tag/%2E%2E/rss", "/tag/..%2Fx", "/tag/a%2Fb"] {
+			assert!(
+				matches!(
+					output_path_for_url(Path::new("/out"), url, Output::Html),
+					Err(ExportError::UnsafeUrlError(unsafe_url)) if unsafe_url == url
+				),
+				"{url} was not rejected"
+			);
+		}
+	}
+}
@@ -0,0 +1,218 @@
+use crate::site::Post;
+use crate::util::{current_datetime, localize_datetime};
+
+const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+const GENERATOR: &str = "PBE - Personal Blog Engine";
+
+#[derive(serde::Serialize)]
+struct JsonFeed<'a> {
+	version: &'static str,
+	title: &'a str,
+	home_page_url: &'a str,
+	feed_url: String,
+	description: &'a str,
+	items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct JsonFeedItem<'a> {
+	id: String,
+	url: String,
+	title: &'a str,
+	content_html: &'a str,
+	summary: &'a str,
+	date_published: String,
+	tags: &'a [String],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedFormat {
+	Rss,
+	Atom,
+	Json,
+}
+
+impl FeedFormat {
+	pub fn content_type(&self) -> &'static str {
+		match self {
+			FeedFormat::Rss => "application/rss+xml",
+			FeedFormat::Atom => "application/atom+xml",
+			FeedFormat::Json => "application/feed+json",
+		}
+	}
+}
+
+/// A feed of posts that can be rendered out in any of the supported feed formats.
+pub struct Feed<'a> {
+	pub title: &'a str,
+	pub description: &'a str,
+	pub site_url: &'a url::Url,
+	/// The url path of this feed itself, e.g. `/atom.xml`.
+	pub feed_path: &'a str,
+	/// The posts to include in the feed, already sorted by date in descending order.
+	pub posts: &'a [&'a Post],
+	/// Whether to include the full content of each post, or only its summary.
+	pub full_content: bool,
+	/// The time zone that post dates are in, or `None` for the local system time zone.
+	pub timezone: Option<&'a chrono_tz::Tz>,
+}
+
+impl<'a> Feed<'a> {
+	fn absolute_url(&self, path: &str) -> String {
+		self.site_url.join(path).map_or_else(|_| path.to_string(), |url| url.to_string())
+	}
+
+	fn content_html(&self, post: &'a Post) -> &'a str {
+		if self.full_content {
+			&post.content_html
+		} else {
+			&post.summary_html
+		}
+	}
+
+	fn post_date(&self, post: &Post) -> chrono::DateTime<chrono::FixedOffset> {
+		localize_datetime(&post.date, self.timezone)
+	}
+
+	/// The date/time the feed was last updated, which is the date/time of the most recent post.
+	fn updated(&self) -> chrono::DateTime<chrono::FixedOffset> {
+		let date = self.posts.first().map_or_else(|| current_datetime(self.timezone), |post| post.date);
+		localize_datetime(&date, self.timezone)
+	}
+
+	pub fn render(&self, format: FeedFormat) -> String {
+		match format {
+			FeedFormat::Rss => self.to_rss(),
+			FeedFormat::Atom => self.to_atom(),
+			FeedFormat::Json => self.to_json(),
+		}
+	}
+
+	fn to_rss(&self) -> String {
+		let mut channel = rss::ChannelBuilder::default() //
+			.title(self.title)
+			.description(self.description)
+			.link(self.site_url.to_string())
+			.last_build_date(self.updated().to_rfc2822())
+			.generator(GENERATOR.to_string())
+			.atom_ext(rss::extension::atom::AtomExtension {
+				links: vec![atom_syndication::LinkBuilder::default()
+					.href(self.absolute_url(self.feed_path))
+					.rel("self")
+					.mime_type(FeedFormat::Rss.content_type().to_string())
+					.build()],
+			})
+			.build();
+		channel.set_items(
+			self.posts
+				.iter()
+				.map(|post| {
+					let url = self.absolute_url(&post.url);
+					let mut item = rss::ItemBuilder::default() //
+						.title(post.title.clone())
+						.link(url.clone())
+						.guid(rss::GuidBuilder::default().value(url).permalink(true).build())
+						.pub_date(self.post_date(post).to_rfc2822())
+						.categories(
+							post.tags
+								.iter()
+								.map(|tag| rss::CategoryBuilder::default().name(tag.clone()).build())
+								.collect::<Vec<rss::Category>>(),
+						)
+						.description(post.summary_html.clone())
+						.build();
+					if self.full_content {
+						item.set_content(post.content_html.clone());
+					}
+					item
+				})
+				.collect::<Vec<rss::Item>>(),
+		);
+		channel.to_string()
+	}
+
+	fn to_atom(&self) -> String {
+		let site_url = self.site_url.to_string();
+		let feed = atom_syndication::FeedBuilder::default()
+			.title(self.title)
+			.generator(atom_syndication::Generator { value: GENERATOR.to_string(), ..Default::default() })
+			.subtitle(atom_syndication::Text::plain(self.description))
+			.id(site_url.clone())
+			.updated(self.updated())
+			.link(
+				atom_syndication::LinkBuilder::default() //
+					.href(self.absolute_url(self.feed_path))
+					.rel("self")
+					.build(),
+			)
+			.link(
+				atom_syndication::LinkBuilder::default() //
+					.href(site_url)
+					.rel("alternate")
+					.build(),
+			)
+			.entries(
+				self.posts
+					.iter()
+					.map(|post| {
+						let url = self.absolute_url(&post.url);
+						let date = self.post_date(post);
+						atom_syndication::EntryBuilder::default()
+							.title(post.title.clone())
+							.id(url.clone())
+							.updated(date)
+							.published(date)
+							.link(
+								atom_syndication::LinkBuilder::default() //
+									.href(url)
+									.rel("alternate")
+									.build(),
+							)
+							.categories(
+								post.tags
+									.iter()
+									.map(|tag| atom_syndication::CategoryBuilder::default().term(tag.clone()).build())
+									.collect::<Vec<atom_syndication::Category>>(),
+							)
+							.summary(atom_syndication::Text::plain(post.description.clone()))
+							.content(
+								atom_syndication::ContentBuilder::default()
+									.value(self.content_html(post).to_string())
+									.content_type("html".to_string())
+									.build(),
+							)
+							.build()
+					})
+					.collect::<Vec<atom_syndication::Entry>>(),
+			)
+			.build();
+		feed.to_string()
+	}
+
+	fn to_json(&self) -> String {
+		let feed = JsonFeed {
+			version: JSON_FEED_VERSION,
+			title: self.title,
+			home_page_url: self.site_url.as_str(),
+			feed_url: self.absolute_url(self.feed_path),
+			description: self.description,
+			items: self
+				.posts
+				.iter()
+				.map(|post| {
+					let url = self.absolute_url(&post.url);
+					JsonFeedItem {
+						id: url.clone(),
+						url,
+						title: &post.title,
+						content_html: self.content_html(post),
+						summary: &post.description,
+						date_published: self.post_date(post).to_rfc3339(),
+						tags: &post.tags,
+					}
+				})
+				.collect(),
+		};
+		serde_json::to_string_pretty(&feed).expect("JSON feed serialization failed")
+	}
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use pulldown_cmark::escape::escape_html;
+
+use crate::site::ContentError;
+use crate::{config, gemtext, markdown};
+
+/// The content format of files whose extension has no content format registered for it. Such content is rendered
+/// out as-is.
+const FALLBACK_FORMAT: &str = "html";
+
+/// The content formats of files with these extensions, unless `content_formats` in `server.yml` says otherwise.
+const DEFAULT_FORMATS: [(&str, &str); 5] =
+	[("md", "markdown"), ("html", "html"), ("htm", "html"), ("txt", "text"), ("gmi", "gemtext")];
+
+/// The output of rendering content in any format.
+#[derive(Clone)]
+pub struct RenderedFormat {
+	pub html: String,
+	pub summary_html: String,
+	/// The languages of any code blocks which could not be syntax highlighted as there was no syntax for them.
+	pub unknown_languages: Vec<String>,
+	/// Problems with the content which did not stop it from being rendered, e.g. invalid code block options.
+	pub warnings: Vec<String>,
+	/// The table of contents of the content. Only Markdown content has one, for all other content this is empty.
+	pub toc: Vec<markdown::TocEntry>,
+}
+
+impl RenderedFormat {
+	/// Content with no code blocks to highlight, no table of contents, and nothing to warn about.
+	fn plain(html: String, summary_html: String) -> Self {
+		RenderedFormat { html, summary_html, unknown_languages: Vec::new(), warnings: Vec::new(), toc: Vec::new() }
+	}
+}
+
+/// Renders content written in a particular format to HTML. The content has already had any front matter removed.
+pub trait FormatRenderer: Send + Sync {
+	fn render(&self, path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError>;
+}
+
+/// Returns the HTML content preceding a "more" marker (e.g. `<!-- more -->`), or all of it if there is no marker.
+fn split_html_summary(html: &str) -> String {
+	let mut offset = 0;
+	while let Some(start) = html[offset..].find("<!--").map(|i| i + offset) {
+		let Some(end) = html[start..].find("-->").map(|i| i + start + 3) else {
+			break;
+		};
+		if markdown::is_more_marker(&html[start..end]) {
+			return html[..start].to_string();
+		}
+		offset = end;
+	}
+	html.to_string()
+}
+
+/// Splits text at the first line consisting of only a "more" marker. Returns the text with that line removed, and
+/// the text preceding it, or all of it if there is no marker.
+fn split_text_summary(text: &str) -> (String, String) {
+	let mut offset = 0;
+	for line in text.split_inclusive('\n') {
+		if markdown::is_more_marker(line) {
+			let summary = &text[..offset];
+			return (format!("{}{}", summary, &text[offset + line.len()..]), summary.to_string());
+		}
+		offset += line.len();
+	}
+	(text.to_string(), text.to_string())
+}
+
+pub struct MarkdownFormat(Arc<markdown::MarkdownRenderer>);
+
+impl FormatRenderer for MarkdownFormat {
+	fn render(&self, path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError> {
+		self.0.render_to_html(raw_content).map_err(|e| ContentError::MarkdownRenderingError(path.to_path_buf(), e))
+	}
+}
+
+/// HTML content, which is rendered out as-is.
+pub struct HtmlFormat;
+
+impl FormatRenderer for HtmlFormat {
+	fn render(&self, _path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError> {
+		Ok(RenderedFormat::plain(raw_content.to_string(), split_html_summary(raw_content)))
+	}
+}
+
+/// Plain text content, which is escaped and rendered out within a `<pre>` so that its formatting is kept.
+pub struct TextFormat;
+
+impl TextFormat {
+	fn to_html(text: &str) -> String {
+		let mut html = String::from("<pre class=\"plain-text\">");
+		escape_html(&mut html, text.trim_end()).unwrap();
+		html.push_str("</pre>");
+		html
+	}
+}
+
+impl FormatRenderer for TextFormat {
+	fn render(&self, _path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError> {
+		let (text, summary) = split_text_summary(raw_content);
+		Ok(RenderedFormat::plain(TextFormat::to_html(&text), TextFormat::to_html(&summary)))
+	}
+}
+
+/// Gemtext content, as used by the Gemini protocol.
+pub struct GemtextFormat;
+
+impl FormatRenderer for GemtextFormat {
+	fn render(&self, _path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError> {
+		let (text, summary) = split_text_summary(raw_content);
+		Ok(RenderedFormat::plain(gemtext::render_to_html(&text), gemtext::render_to_html(&summary)))
+	}
+}
+
+/// Returns the lowercased extension of the given path, which is what content formats are looked up by.
+fn extension_of(path: &Path) -> String {
+	path.extension().unwrap_or_default().to_string_lossy().to_lowercase()
+}
+
+/// Returns true if the file at the given path has an extension that a content format is used for, either by default
+/// or because of `content_formats` in `server.yml`. Files with any other extension are still rendered (as HTML) if
+/// they are listed in `posts.yml` or `pages.yml`, but are not considered to be content when discovering files.
+pub fn has_content_format(server_config: &config::Server, path: &Path) -> bool {
+	let extension = extension_of(path);
+	DEFAULT_FORMATS.iter().any(|(default_extension, _)| *default_extension == extension)
+		|| server_config.content_formats.keys().any(|configured| normalize_extension(configured) == extension)
+}
+
+/// Normalizes an extension given in `server.yml`, which may have a leading dot and be in any case.
+fn normalize_extension(extension: &str) -> String {
+	extension.trim_start_matches('.').to_lowercase()
+}
+
+/// The renderers for every content format, along with which file extensions are rendered using which format.
+pub struct ContentFormats {
+	/// Content format renderers, keyed by the name of the format.
+	renderers: HashMap<String, Box<dyn FormatRenderer>>,
+	/// Content format names, keyed by lowercased file extension.
+	extensions: HashMap<String, String>,
+}
+
+impl ContentFormats {
+	/// Returns the built-in content formats, with file extensions mapped to them by default.
+	pub fn new(markdown_renderer: Arc<markdown::MarkdownRenderer>) -> Self {
+		let mut formats = ContentFormats {
+			renderers: HashMap::new(),
+			extensions: DEFAULT_FORMATS
+				.iter()
+				.map(|(extension, name)| (extension.to_string(), name.to_string()))
+				.collect(),
+		};
+		formats.register("markdown", MarkdownFormat(markdown_renderer));
+		formats.register("html", HtmlFormat);
+		formats.register("text", TextFormat);
+		formats.register("gemtext", GemtextFormat);
+		formats
+	}
+
+	/// Registers the renderer for a content format under the given name, replacing any existing renderer with that
+	/// name. File extensions can then be mapped to the format by name with `content_formats` in `server.yml`. Only
+	/// the built-in formats have any file extensions mapped to them by default.
+	pub fn register(&mut self, name: &str, renderer: impl FormatRenderer + 'static) {
+		self.renderers.insert(name.to_string(), Box::new(renderer));
+	}
+
+	/// Maps file extensions to content formats as given by `content_formats` in `server.yml`, which takes precedence
+	/// over the default mappings. Every format must have been registered beforehand.
+	pub fn map_configured_extensions(&mut self, server_config: &config::Server) -> Result<(), ContentError> {
+		for (extension, name) in server_config.content_formats.iter() {
+			if !self.renderers.contains_key(name) {
+				return Err(ContentError::UnknownFormatError(extension.clone(), name.clone()));
+			}
+			self.extensions.insert(normalize_extension(extension), name.clone());
+		}
+		Ok(())
+	}
+
+	/// Returns the renderer for the content file at the given path, based on its file extension.
+	pub fn renderer_for(&self, path: &Path) -> &dyn FormatRenderer {
+		let name = self.extensions.get(&extension_of(path)).map_or(FALLBACK_FORMAT, String::as_str);
+		// every format name in the extensions map was checked to have a renderer when it was added
+		self.renderers[name].as_ref()
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+	use crate::config::tests::server_config;
+
+	fn formats(server_config: &config::Server) -> ContentFormats {
+		ContentFormats::new(Arc::new(markdown::MarkdownRenderer::new(server_config).unwrap()))
+	}
+
+	fn render(formats: &ContentFormats, path: &str, raw_content: &str) -> RenderedFormat {
+		let path = Path::new(path);
+		formats.renderer_for(path).render(path, raw_content).unwrap()
+	}
+
+	/// Renders content as uppercased text, as a stand-in for a custom content format.
+	pub struct ShoutingFormat;
+
+	impl FormatRenderer for ShoutingFormat {
+		fn render(&self, _path: &Path, raw_content: &str) -> Result<RenderedFormat, ContentError> {
+			let html = raw_content.to_uppercase();
+			Ok(RenderedFormat::plain(html.clone(), html))
+		}
+	}
+
+	#[test]
+	fn html_summary_ends_at_a_more_marker() {
+		assert_eq!(split_html_summary("<p>one</p><!-- MORE --><p>two</p>"), "<p>one</p>");
+		assert_eq!(split_html_summary("<!-- comment --><p>one</p>"), "<!-- comment --><p>one</p>");
+		assert_eq!(split_html_summary("<p>one</p><!-- unclosed"), "<p>one</p><!-- unclosed");
+	}
+
+	#[test]
+	fn text_summary_ends_at_a_line_with_only_a_more_marker() {
+		assert_eq!(
+			split_text_summary("one\n <!-- more -->\ntwo\n"),
+			(String::from("one\ntwo\n"), String::from("one\n"))
+		);
+		assert_eq!(
+			split_text_summary("one <!-- more -->\ntwo"),
+			(String::from("one <!-- more -->\ntwo"), String::from("one <!-- more -->\ntwo"))
+		);
+	}
+
+	#[test]
+	fn content_formats_are_found_by_extension() {
+		let default_config = server_config("");
+		assert!(has_content_format(&default_config, Path::new("posts/hello.md")));
+		assert!(has_content_format(&default_config, Path::new("notes.TXT")));
+		assert!(has_content_format(&default_config, Path::new("capsule.gmi")));
+		assert!(!has_content_format(&default_config, Path::new("image.png")));
+		assert!(!has_content_format(&default_config, Path::new("README")));
+		assert!(!has_content_format(&default_config, Path::new("server.log")));
+
+		let configured = server_config("content_formats:\n  .Log: text\n");
+		assert!(has_content_format(&configured, Path::new("server.log")));
+	}
+
+	#[test]
+	fn text_is_escaped_within_a_pre() {
+		let formats = formats(&server_config(""));
+		let rendered = render(&formats, "notes.txt", "a < b\n  <!-- more -->\nc & d\n\n");
+		assert_eq!(rendered.html, "<pre class=\"plain-text\">a &lt; b\nc &amp; d</pre>");
+		assert_eq!(rendered.summary_html, "<pre class=\"plain-text\">a &lt; b</pre>");
+	}
+
+	#[test]
+	fn unknown_extensions_are_rendered_as_is() {
+		let formats = formats(&server_config(""));
+		assert_eq!(render(&formats, "page.xyz", "<b>*hi*</b>").html, "<b>*hi*</b>");
+		assert_eq!(render(&formats, "page", "*hi*").html, "*hi*");
+	}
+
+	#[test]
+	fn configured_extensions_take_precedence_over_the_defaults() {
+		let server_config = server_config("content_formats:\n  md: text\n  .Markdown: markdown\n");
+		let mut formats = formats(&server_config);
+		formats.map_configured_extensions(&server_config).unwrap();
+		assert_eq!(render(&formats, "post.md", "*hi*").html, "<pre class=\"plain-text\">*hi*</pre>");
+		assert_eq!(render(&formats, "post.markdown", "*hi*").html, "<p><em>hi</em></p>\n");
+	}
+
+	#[test]
+	fn configured_extensions_can_be_mapped_to_registered_formats() {
+		let server_config = server_config("content_formats:\n  shout: shouting\n");
+		let mut formats = formats(&server_config);
+		formats.register("shouting", ShoutingFormat);
+		formats.map_configured_extensions(&server_config).unwrap();
+		assert_eq!(render(&formats, "post.shout", "hello").html, "HELLO");
+	}
+
+	#[test]
+	fn configured_extensions_must_be_mapped_to_registered_formats() {
+		let server_config = server_config("content_formats:\n  shout: shouting\n");
+		let mut formats = formats(&server_config);
+		assert!(matches!(
+			formats.map_configured_extensions(&server_config),
+			Err(ContentError::UnknownFormatError(extension, name)) if extension == "shout" && name == "shouting"
+		));
+	}
+}
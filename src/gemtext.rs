@@ -0,0 +1,130 @@
+use pulldown_cmark::escape::{escape_href, escape_html};
+
+fn push_escaped(html: &mut String, text: &str) {
+	escape_html(&mut *html, text).unwrap();
+}
+
+fn push_element(html: &mut String, tag: &str, text: &str) {
+	html.push_str(&format!("<{}>", tag));
+	push_escaped(html, text.trim());
+	html.push_str(&format!("</{}>\n", tag));
+}
+
+/// Renders Gemtext, the line-oriented markup used by the Gemini protocol, to HTML. Every line is either a heading,
+/// a link, a list item, a quote, a toggle for preformatted text, or a paragraph of text.
+pub fn render_to_html(gemtext: &str) -> String {
+	let mut html = String::with_capacity(gemtext.len() * 2);
+	let mut is_in_list = false;
+	let mut is_preformatted = false;
+
+	for line in gemtext.lines() {
+		if is_preformatted {
+			if line.starts_with("```") {
+				html.push_str("</pre>\n");
+				is_preformatted = false;
+			} else {
+				push_escaped(&mut html, line);
+				html.push('\n');
+			}
+			continue;
+		}
+
+		let list_item = line.strip_prefix("* ");
+		if is_in_list && list_item.is_none() {
+			html.push_str("</ul>\n");
+			is_in_list = false;
+		}
+
+		if let Some(alt_text) = line.strip_prefix("```") {
+			// the alt text describes the preformatted text, e.g. what language it is written in or what it depicts
+			let alt_text = alt_text.trim();
+			if alt_text.is_empty() {
+				html.push_str("<pre>");
+			} else {
+				html.push_str("<pre aria-label=\"");
+				push_escaped(&mut html, alt_text);
+				html.push_str("\">");
+			}
+			is_preformatted = true;
+		} else if let Some(link) = line.strip_prefix("=>") {
+			let link = link.trim();
+			let (url, label) = match link.split_once(char::is_whitespace) {
+				Some((url, label)) => (url, label.trim()),
+				None => (link, link),
+			};
+			html.push_str("<p><a href=\"");
+			escape_href(&mut html, url).unwrap();
+			html.push_str("\">");
+			push_escaped(&mut html, label);
+			html.push_str("</a></p>\n");
+		} else if let Some(heading) = line.strip_prefix("###") {
+			push_element(&mut html, "h3", heading);
+		} else if let Some(heading) = line.strip_prefix("##") {
+			push_element(&mut html, "h2", heading);
+		} else if let Some(heading) = line.strip_prefix('#') {
+			push_element(&mut html, "h1", heading);
+		} else if let Some(list_item) = list_item {
+			if !is_in_list {
+				html.push_str("<ul>\n");
+				is_in_list = true;
+			}
+			push_element(&mut html, "li", list_item);
+		} else if let Some(quote) = line.strip_prefix('>') {
+			html.push_str("<blockquote>");
+			push_element(&mut html, "p", quote);
+			html.push_str("</blockquote>\n");
+		} else if !line.trim().is_empty() {
+			push_element(&mut html, "p", line);
+		}
+	}
+
+	if is_in_list {
+		html.push_str("</ul>\n");
+	}
+	if is_preformatted {
+		html.push_str("</pre>\n");
+	}
+	html
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn headings_quotes_and_paragraphs_are_rendered() {
+		assert_eq!(
+			render_to_html("# One\n## Two\n### Three\n\n> quoted\ntext & more\n   \n"),
+			"<h1>One</h1>\n<h2>Two</h2>\n<h3>Three</h3>\n<blockquote><p>quoted</p>\n</blockquote>\n<p>text &amp; more</p>\n"
+		);
+	}
+
+	#[test]
+	fn links_are_rendered_with_their_label_or_url() {
+		assert_eq!(
+			render_to_html("=> https://example.com/?a=1&b=2   An  example\n=>/about\n"),
+			"<p><a href=\"https://example.com/?a=1&amp;b=2\">An  example</a></p>\n<p><a href=\"/about\">/about</a></p>\n"
+		);
+	}
+
+	#[test]
+	fn consecutive_list_items_are_grouped_into_one_list() {
+		assert_eq!(
+			render_to_html("* one\n* two\ntext\n* three"),
+			"<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<p>text</p>\n<ul>\n<li>three</li>\n</ul>\n"
+		);
+	}
+
+	#[test]
+	fn preformatted_text_is_toggled_and_kept_as_is() {
+		assert_eq!(
+			render_to_html("* item\n``` ascii <art>\n# not a heading\n  * not a list\n```\n=> /after"),
+			"<ul>\n<li>item</li>\n</ul>\n<pre aria-label=\"ascii &lt;art&gt;\"># not a heading\n  * not a list\n</pre>\n<p><a href=\"/after\">/after</a></p>\n"
+		);
+	}
+
+	#[test]
+	fn unclosed_preformatted_text_is_closed_at_the_end() {
+		assert_eq!(render_to_html("```\ncode"), "<pre>code\n</pre>\n");
+	}
+}
@@ -0,0 +1,91 @@
+use std::convert::Infallible;
+
+use actix_web::web::Bytes;
+use futures_util::Stream;
+use tokio::sync::broadcast;
+
+use crate::util::insert_before_body_end;
+
+/// Injected into served HTML in dev mode. Reloads the page whenever the site is successfully re-generated, or shows
+/// the error in an overlay if re-generation failed.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+	var source = new EventSource("/__pbe/live-reload");
+	source.addEventListener("reload", function () {
+		location.reload();
+	});
+	source.addEventListener("build-error", function (event) {
+		var overlay = document.getElementById("__pbe-error-overlay");
+		if (!overlay) {
+			overlay = document.createElement("pre");
+			overlay.id = "__pbe-error-overlay";
+			overlay.title = "Click to dismiss";
+			overlay.style.cssText = "position: fixed; inset: 0; z-index: 2147483647; margin: 0; padding: 2em; overflow: auto; background: rgba(0, 0, 0, 0.9); color: #ff8080; font: 14px monospace; white-space: pre-wrap;";
+			overlay.addEventListener("click", function () {
+				overlay.remove();
+			});
+			document.body.appendChild(overlay);
+		}
+		overlay.textContent = "Site re-generation failed:\n\n" + event.data;
+	});
+})();
+</script>
+"#;
+
+#[derive(Debug, Clone)]
+pub enum ReloadEvent {
+	Reloaded,
+	Failed(String),
+}
+
+impl ReloadEvent {
+	/// Formats this event as a server-sent event message.
+	fn to_message(&self) -> String {
+		match self {
+			ReloadEvent::Reloaded => String::from("event: reload\ndata:\n\n"),
+			ReloadEvent::Failed(error) => {
+				// each line of the data needs its own "data:" field, the browser joins them back together again
+				let data = error.lines().map(|line| format!("data: {line}\n")).collect::<String>();
+				format!("event: build-error\n{data}\n")
+			}
+		}
+	}
+}
+
+/// Notifies all connected browsers when the site content is re-generated, via server-sent events.
+pub struct LiveReload {
+	sender: broadcast::Sender<ReloadEvent>,
+}
+
+impl LiveReload {
+	pub fn new() -> Self {
+		let (sender, _) = broadcast::channel(16);
+		LiveReload { sender }
+	}
+
+	pub fn notify(&self, event: ReloadEvent) {
+		// an error here only means that there are no browsers currently connected, which is fine
+		let _ = self.sender.send(event);
+	}
+
+	/// Returns a stream of server-sent event messages for a single connected browser.
+	pub fn event_stream(&self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+		let receiver = self.sender.subscribe();
+		let connected = futures_util::stream::once(async { Ok(Bytes::from_static(b": connected\n\n")) });
+		let events = futures_util::stream::unfold(receiver, |mut receiver| async move {
+			loop {
+				match receiver.recv().await {
+					Ok(event) => return Some((Ok(Bytes::from(event.to_message())), receiver)),
+					Err(broadcast::error::RecvError::Lagged(_)) => continue,
+					Err(broadcast::error::RecvError::Closed) => return None,
+				}
+			}
+		});
+		futures_util::StreamExt::chain(connected, events)
+	}
+}
+
+/// Adds the live reload script to the given HTML, just before the closing `</body>` tag if there is one.
+pub fn inject_script(html: &mut String) {
+	insert_before_body_end(html, LIVE_RELOAD_SCRIPT);
+}
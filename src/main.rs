@@ -5,50 +5,132 @@ use actix_files::Files;
 use actix_web::{web, App, HttpServer};
 use anyhow::Context;
 
+mod check;
 mod config;
+mod export;
+mod feeds;
+mod formats;
+mod gemtext;
+mod live_reload;
 mod markdown;
 mod routes;
 mod site;
+mod sitemap;
+mod status;
 mod util;
 mod watcher;
 
+/// Which kinds of files were changed, as reported by the filesystem watcher.
+#[derive(Debug, Default)]
+struct ChangedFiles {
+	server_config: bool,
+	syntaxes: bool,
+	configs: bool,
+	templates: bool,
+	content: Vec<PathBuf>,
+}
+
+impl ChangedFiles {
+	fn from_events(events: &[watcher::DebouncedEvent], site_root: &PathBuf, server_config: &config::Server) -> Self {
+		let (server_config_path, pages_config_path, posts_config_path) = config_paths(site_root);
+		let mut changed = ChangedFiles::default();
+		for path in events.iter().map(|event| &event.path) {
+			if *path == server_config_path {
+				changed.server_config = true;
+			} else if *path == pages_config_path || *path == posts_config_path {
+				changed.configs = true;
+			} else if server_config.syntaxes_path.as_ref().is_some_and(|syntaxes_path| path.starts_with(syntaxes_path))
+				|| server_config.syntax_theme_file().is_some_and(|theme_path| *path == theme_path)
+			{
+				changed.syntaxes = true;
+			} else if path.starts_with(&server_config.templates_path) {
+				changed.templates = true;
+			} else if (path.starts_with(&server_config.pages_path) || path.starts_with(&server_config.posts_path))
+				&& !changed.content.contains(path)
+			{
+				changed.content.push(path.clone());
+			}
+		}
+		changed
+	}
+
+	fn is_empty(&self) -> bool {
+		!self.server_config && !self.syntaxes && !self.configs && !self.templates && self.content.is_empty()
+	}
+}
+
+fn refresh_site(changed: &ChangedFiles, site_root: &PathBuf, data: &site::SiteService) -> anyhow::Result<()> {
+	let (server_config_path, pages_config_path, posts_config_path) = config_paths(site_root);
+	if changed.server_config || changed.syntaxes {
+		// the server config affects pretty much everything, including how content is rendered, and the syntaxes are
+		// only loaded when the content renderer is created, so either of these means rebuilding absolutely everything
+		let server_config = if changed.server_config {
+			log::info!("Server config changed, reloading it and rebuilding everything");
+			config::load_server(&server_config_path, site_root).context("Reloading server config")?
+		} else {
+			log::info!("Syntax definition(s) or theme changed, rebuilding everything");
+			data.server_config().as_ref().clone()
+		};
+		let (pages_config, posts_config) = config::load_content(&pages_config_path, &posts_config_path, &server_config) //
+			.context("Reloading content configs")?;
+		data.rebuild(server_config, pages_config, posts_config).context("Rebuilding SiteContent")?;
+	} else if changed.configs || !changed.content.is_empty() {
+		// the content configs are reloaded if they changed, but also if content files were added or removed, as that
+		// changes them when content discovery is turned on. otherwise only the changed content files are rendered
+		// again, and the rest of the content is taken from the render cache without even reading it again
+		let reusable_configs = if changed.configs {
+			log::info!("Content configs changed, re-generating all content");
+			data.content_renderer().clear_cache();
+			None
+		} else {
+			log::info!("Content file(s) changed, re-generating content from {:?}", changed.content);
+			data.reusable_content_configs(&changed.content)
+		};
+		let (pages_config, posts_config) = match reusable_configs {
+			Some(configs) => configs,
+			None => config::load_content(&pages_config_path, &posts_config_path, &data.server_config()) //
+				.context("Reloading content configs")?,
+		};
+		let changed_paths = (!changed.configs).then_some(changed.content.as_slice());
+		data.refresh_content(pages_config, posts_config, changed.configs || changed.templates, changed_paths)
+			.context("Re-generating SiteContent")?;
+	} else if changed.templates {
+		log::info!("Template(s) changed, reloading templates");
+		data.refresh_templates().context("Reloading templates")?;
+	}
+	Ok(())
+}
+
 fn spawn_watcher(
 	watch_paths: Vec<PathBuf>,
-	pages_config_path: PathBuf,
-	posts_config_path: PathBuf,
+	site_root: PathBuf,
 	data: web::Data<site::SiteService>,
 ) -> tokio::task::JoinHandle<()> {
 	log::info!("Spawning filesystem watcher for paths {:?}", watch_paths);
 	tokio::spawn(async move {
-		watcher::debounce_watch(&watch_paths, move |event| {
+		watcher::debounce_watch(&watch_paths, move |event: watcher::DeboundedEventResult| {
 			match event {
-				Ok(_) => {
-					// right now we don't actually care which file was modified. just always rebuild the whole thing.
-					// this is also why using a debounced watch is important and probably should use a somewhat long
-					// debounce time in practice, just in case someone is doing a lengthy file upload to a remote
-					// server or something of that nature which takes more than 1-2 seconds.
-					// TODO: maybe try to selectively rebuild only what was changed? meh.
-					log::warn!(
-						"Modification to file(s) in watched paths detected, beginning re-generation of SiteContent"
-					);
-
-					log::info!("Reloading content configs");
-					let (pages_config, posts_config) =
-						match config::load_content(&pages_config_path, &posts_config_path, &data.server_config) {
-							Ok(configs) => configs,
-							Err(err) => {
-								log::error!("Error reloading content configs: {:?}", err);
-								return;
-							}
-						};
-
-					log::info!("Re-generating SiteContent");
-					if let Err(err) = data.refresh_content(pages_config, posts_config) {
-						log::error!("Error re-generating SiteContent: {:?}", err);
+				Ok(events) => {
+					// using a debounced watch is important and probably should use a somewhat long debounce time in
+					// practice, just in case someone is doing a lengthy file upload to a remote server or something
+					// of that nature which takes more than 1-2 seconds.
+					let changed = ChangedFiles::from_events(&events, &site_root, &data.server_config());
+					if changed.is_empty() {
 						return;
 					}
+					log::warn!("Modification to file(s) in watched paths detected, beginning refresh of SiteContent");
 
-					log::info!("Finished re-generating SiteContent");
+					// if this fails, the existing content is left as-is and continues being served
+					match refresh_site(&changed, &site_root, &data) {
+						Ok(()) => {
+							log::info!("Finished refreshing SiteContent");
+							data.refresh_finished(Ok(()));
+						}
+						Err(err) => {
+							log::error!("Error refreshing SiteContent: {:?}", err);
+							data.refresh_finished(Err(err.as_ref()));
+						}
+					}
 				}
 				Err(errors) => {
 					for error in errors {
@@ -62,6 +144,137 @@ fn spawn_watcher(
 	})
 }
 
+fn config_paths(site_root: &PathBuf) -> (PathBuf, PathBuf, PathBuf) {
+	let server_config_path: PathBuf = [site_root, &"server.yml".into()].iter().collect();
+	let pages_config_path: PathBuf = [site_root, &"pages.yml".into()].iter().collect();
+	let posts_config_path: PathBuf = [site_root, &"posts.yml".into()].iter().collect();
+	(server_config_path, pages_config_path, posts_config_path)
+}
+
+fn load_site(site_root: &PathBuf) -> anyhow::Result<site::SiteService> {
+	let (server_config_path, pages_config_path, posts_config_path) = config_paths(site_root);
+
+	log::info!("Loading config ...");
+	let server_config = config::load_server(&server_config_path, site_root) //
+		.context("Loading server config")?;
+	let (pages_config, posts_config) = config::load_content(&pages_config_path, &posts_config_path, &server_config) //
+		.context("Loading content configs")?;
+
+	log::info!("Initializing site data and content ...");
+	site::SiteService::new(server_config, pages_config, posts_config) //
+		.context("Constructing SiteService instance")
+}
+
+fn export(site_root: &PathBuf, output_path: &Path) -> anyhow::Result<()> {
+	log::info!("Using site root {:?}", site_root);
+	let site_service = load_site(site_root)?;
+
+	log::info!("Exporting site to {:?} ...", output_path);
+	export::export_site(&site_service, output_path).context("Exporting site")?;
+
+	log::info!("Finished!");
+	Ok(())
+}
+
+fn check(site_root: &PathBuf) -> anyhow::Result<()> {
+	log::info!("Checking site root {:?}", site_root);
+	let (server_config_path, pages_config_path, posts_config_path) = config_paths(site_root);
+	let problems = check::check_site(site_root, &server_config_path, &pages_config_path, &posts_config_path);
+	if problems.is_empty() {
+		println!("No problems found.");
+		return Ok(());
+	}
+	for problem in problems.iter() {
+		println!("{}", problem);
+	}
+	Err(anyhow::anyhow!("Found {} problem(s) with the site", problems.len()))
+}
+
+async fn serve(site_root: &PathBuf) -> anyhow::Result<()> {
+	log::info!("Using site root {:?}", site_root);
+
+	let site_service = load_site(site_root)?;
+	let server_config = site_service.server_config().as_ref().clone();
+	let data = web::Data::new(site_service);
+
+	// the whole site root is watched, so that any of the paths in server.yml can be changed while running. changes
+	// to anything that isn't cached by us (e.g. the static files) are simply ignored though. any paths in server.yml
+	// which are outside of the site root are also watched, but only those paths that were set when starting up
+	let mut watch_paths = vec![site_root.clone()];
+	let mut content_paths =
+		vec![server_config.pages_path.clone(), server_config.posts_path.clone(), server_config.templates_path.clone()];
+	content_paths.extend(server_config.syntaxes_path.clone());
+	content_paths.extend(server_config.syntax_theme_file());
+	watch_paths.extend(content_paths.into_iter().filter(|path| !path.starts_with(site_root)));
+	let watcher_handle = spawn_watcher(watch_paths, site_root.clone(), data.clone());
+
+	log::info!(
+		"Spawning HTTP server for site, listening on {}:{} ...",
+		server_config.bind_addr,
+		server_config.bind_port
+	);
+
+	HttpServer::new(move || {
+		App::new() //
+			.app_data(data.clone())
+			.wrap(actix_web::middleware::NormalizePath::trim())
+			.wrap(
+				actix_web::middleware::ErrorHandlers::new()
+					.handler(actix_web::http::StatusCode::NOT_FOUND, routes::not_found_page)
+					.default_handler_server(routes::error_page),
+			)
+			.service(routes::latest_posts)
+			.service(routes::latest_posts_by_tag)
+			.service(routes::latest_posts_by_tag_page)
+			.service(routes::posts_archive)
+			.service(routes::posts_archive_page)
+			.service(routes::rss_feed)
+			.service(routes::tag_rss_feed)
+			.service(routes::sitemap)
+			.service(routes::refresh_status)
+			.configure(|config| {
+				if server_config.dev_mode {
+					config.service(routes::live_reload);
+				}
+			})
+			.service(
+				web::resource(&server_config.feeds.atom_path)
+					.route(web::get().to(routes::atom_feed))
+					.route(web::head().to(routes::atom_feed)),
+			)
+			.service(
+				web::resource(&server_config.feeds.json_feed_path)
+					.route(web::get().to(routes::json_feed))
+					.route(web::head().to(routes::json_feed)),
+			)
+			.service(
+				web::resource(format!("/tag/{{tag}}{}", server_config.feeds.atom_path))
+					.route(web::get().to(routes::tag_atom_feed))
+					.route(web::head().to(routes::tag_atom_feed)),
+			)
+			.service(
+				web::resource(format!("/tag/{{tag}}{}", server_config.feeds.json_feed_path))
+					.route(web::get().to(routes::tag_json_feed))
+					.route(web::head().to(routes::tag_json_feed)),
+			)
+			.service(Files::new("/", &server_config.static_files_path))
+			.default_service(web::get().to(routes::site_content))
+	})
+	// live reload connections stay open until the browser goes away, so don't bother waiting on them when shutting down
+	.shutdown_timeout(if server_config.dev_mode { 1 } else { 30 })
+	.bind((server_config.bind_addr.clone(), server_config.bind_port))
+	.with_context(|| format!("Binding HTTP server on {}:{}", server_config.bind_addr, server_config.bind_port))?
+	.run()
+	.await
+	.map_err(anyhow::Error::from)?;
+
+	log::info!("Aborting filesystem watcher");
+	watcher_handle.abort();
+
+	log::info!("Finished!");
+	Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
 	let log_level = env::var("LOG_LEVEL").map_or(String::from("info"), |value| value.to_lowercase());
@@ -93,69 +306,30 @@ async fn main() -> anyhow::Result<()> {
 	let first_arg = args.first().unwrap_or(&String::new()).to_lowercase();
 	if first_arg == "-h" || first_arg == "--help" {
 		println!("Usage: pbe <SITE_ROOT>");
+		println!("       pbe export <SITE_ROOT> <OUT_DIR>");
+		println!("       pbe check <SITE_ROOT>");
 		println!("Where SITE_ROOT is a path that contains the config files and all content and web resources.");
+		println!("The export command renders the entire site out as static files into OUT_DIR instead of serving it.");
+		println!("The check command reports all problems found with the site, exiting with an error if there are any.");
 		Ok(())
+	} else if first_arg == "export" {
+		let (Some(site_root), Some(output_path)) = (args.get(1), args.get(2)) else {
+			return Err(anyhow::anyhow!("Usage: pbe export <SITE_ROOT> <OUT_DIR>"));
+		};
+		let site_root = Path::new(site_root).canonicalize()?;
+		export(&site_root, Path::new(output_path))
+	} else if first_arg == "check" {
+		let Some(site_root) = args.get(1) else {
+			return Err(anyhow::anyhow!("Usage: pbe check <SITE_ROOT>"));
+		};
+		let site_root = Path::new(site_root).canonicalize()?;
+		check(&site_root)
 	} else {
 		let site_root = if first_arg.is_empty() {
 			env::current_dir()? //
 		} else {
 			Path::new(&first_arg).canonicalize()?
 		};
-		log::info!("Using site root {:?}", site_root);
-
-		let server_config_path: PathBuf = [&site_root, &"server.yml".into()].iter().collect();
-		let pages_config_path: PathBuf = [&site_root, &"pages.yml".into()].iter().collect();
-		let posts_config_path: PathBuf = [&site_root, &"posts.yml".into()].iter().collect();
-
-		log::info!("Loading config ...");
-		let server_config = config::load_server(&server_config_path, &site_root) //
-			.context("Loading server config")?;
-		let (pages_config, posts_config) = config::load_content(&pages_config_path, &posts_config_path, &server_config) //
-			.context("Loading content configs")?;
-
-		log::info!("Initializing site data and content ...");
-		let site_service = site::SiteService::new(server_config.clone(), pages_config, posts_config)
-			.context("Constructing SiteService instance")?;
-		let data = web::Data::new(site_service);
-
-		// note that we do not want to watch the static files path. there's no need, as nothing in there that is
-		// being cached by us here
-		let watch_paths = vec![
-			pages_config_path.clone(),
-			posts_config_path.clone(),
-			server_config.pages_path.clone(),
-			server_config.posts_path.clone(),
-			server_config.templates_path.clone(),
-		];
-		let watcher_handle = spawn_watcher(watch_paths, pages_config_path, posts_config_path, data.clone());
-
-		log::info!(
-			"Spawning HTTP server for site, listening on {}:{} ...",
-			server_config.bind_addr,
-			server_config.bind_port
-		);
-
-		HttpServer::new(move || {
-			App::new() //
-				.app_data(data.clone())
-				.wrap(actix_web::middleware::NormalizePath::trim())
-				.service(routes::latest_posts)
-				.service(routes::latest_posts_by_tag)
-				.service(routes::posts_archive)
-				.service(routes::rss_feed)
-				.service(Files::new("/", &server_config.static_files_path))
-				.default_service(web::get().to(routes::site_content))
-		})
-		.bind((server_config.bind_addr.clone(), server_config.bind_port))
-		.with_context(|| format!("Binding HTTP server on {}:{}", server_config.bind_addr, server_config.bind_port))?
-		.run()
-		.await
-		.map_err(anyhow::Error::from)?;
-
-		log::info!("Aborting filesystem watcher");
-		watcher_handle.abort();
-
-		log::info!("Finished!");
-		Ok(())
+		serve(&site_root).await
 	}
 }
@@ -1,11 +1,21 @@
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::escape::escape_html;
 use pulldown_cmark::{CodeBlockKind, CowStr, Event, Parser, Tag};
-use syntect::html::{ClassStyle, ClassedHTMLGenerator};
-use syntect::parsing::SyntaxSet;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+	css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html, ClassStyle,
+	ClassedHTMLGenerator, IncludeBackground,
+};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
 use syntect::util::LinesWithEndings;
 
 use crate::config;
+use crate::formats::RenderedFormat;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, thiserror::Error)]
 pub enum MarkdownError {
 	#[error("Syntax highlighting error")]
@@ -13,14 +23,200 @@ pub enum MarkdownError {
 
 	#[error("Syntax loading error")]
 	SyntectLoadingError(#[from] syntect::LoadingError),
+
+	#[error("No built-in syntax theme named {0}")]
+	UnknownThemeError(String),
+}
+
+/// All of the CSS classes in syntax highlighted HTML are prefixed with this.
+const CLASS_PREFIX: &str = "sh-";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: CLASS_PREFIX };
+
+/// Aliases for code block languages which are commonly used but that don't match the file extension or name of any
+/// of the default syntaxes. Aliases configured in `server.yml` take precedence over these.
+const DEFAULT_LANGUAGE_ALIASES: [(&str, &str); 4] =
+	[("shell", "sh"), ("zsh", "sh"), ("golang", "go"), ("csharp", "cs")];
+
+/// Marks the point in Markdown or HTML content at which the summary of that content ends.
+pub const MORE_MARKER: &str = "<!--more-->";
+
+/// Returns true if the given HTML is a [`MORE_MARKER`], ignoring whitespace and case, e.g. `<!-- more -->`.
+pub fn is_more_marker(html: &str) -> bool {
+	html.split_whitespace().collect::<String>().eq_ignore_ascii_case(MORE_MARKER)
+}
+
+/// Turns the text of a heading into a url-friendly id, e.g. `Hello, World!` becomes `hello-world`.
+pub fn slugify(text: &str) -> String {
+	let mut slug = String::new();
+	for c in text.trim().chars().flat_map(char::to_lowercase) {
+		if c.is_alphanumeric() {
+			slug.push(c);
+		} else if (c.is_whitespace() || c == '-' || c == '_') && !slug.is_empty() && !slug.ends_with('-') {
+			slug.push('-');
+		}
+	}
+	let slug = slug.trim_end_matches('-');
+	if slug.is_empty() {
+		String::from("section")
+	} else {
+		slug.to_string()
+	}
+}
+
+/// An entry in the table of contents of some Markdown content, for one of its headings.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TocEntry {
+	/// The heading level, from 1 to 6.
+	pub level: usize,
+	pub title: String,
+	pub id: String,
+	/// The entries for the headings which follow this one with a deeper level, up until the next heading with the same
+	/// or a shallower level.
+	pub children: Vec<TocEntry>,
+}
+
+/// Nests a flat list of table of contents entries, in the order they appear in the content, under their parents.
+fn nest_toc_entries(entries: &mut std::iter::Peekable<std::vec::IntoIter<TocEntry>>, level: usize) -> Vec<TocEntry> {
+	let mut nested = Vec::new();
+	while let Some(mut entry) = entries.next_if(|entry| entry.level > level) {
+		entry.children = nest_toc_entries(entries, entry.level);
+		nested.push(entry);
+	}
+	nested
+}
+
+/// Removes any id given explicitly to a heading (e.g. `## Heading {#my-id}`), leaving other events as-is.
+fn without_heading_id(event: Event) -> Event {
+	match event {
+		Event::Start(Tag::Heading(level, _, classes)) => Event::Start(Tag::Heading(level, None, classes)),
+		event => event,
+	}
+}
+
+/// Splits a code block fence info string on whitespace, except for whitespace inside double quotes. The quotes
+/// themselves are removed.
+fn split_info_string(info: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut token = String::new();
+	let mut is_in_quotes = false;
+	for c in info.chars() {
+		match c {
+			'"' => is_in_quotes = !is_in_quotes,
+			c if c.is_whitespace() && !is_in_quotes => {
+				if !token.is_empty() {
+					tokens.push(std::mem::take(&mut token));
+				}
+			}
+			c => token.push(c),
+		}
+	}
+	if !token.is_empty() {
+		tokens.push(token);
+	}
+	tokens
+}
+
+/// The options for a code block, as given in the info string of its fence, e.g.
+/// ```` ```rust title="main.rs" linenos hl_lines="3-5 8" ````.
+#[derive(Debug, Default)]
+struct CodeBlockInfo {
+	language: String,
+	title: Option<String>,
+	line_numbers: bool,
+	/// Ranges of line numbers, starting at 1, which are highlighted. Each range includes both its start and end.
+	highlighted_lines: Vec<(usize, usize)>,
+}
+
+impl CodeBlockInfo {
+	/// Parses the info string of a code block fence, returning the options along with warnings describing any parts
+	/// of it which were invalid and so were ignored.
+	fn parse(info: &str) -> (Self, Vec<String>) {
+		let mut code_block_info = CodeBlockInfo::default();
+		let mut warnings = Vec::new();
+		for (index, token) in split_info_string(info).into_iter().enumerate() {
+			match token.split_once('=') {
+				None if token == "linenos" => code_block_info.line_numbers = true,
+				None if index == 0 => code_block_info.language = token,
+				Some(("title", title)) => code_block_info.title = Some(title.to_string()),
+				Some(("hl_lines", lines)) => {
+					for range in lines.split([' ', ',']).filter(|range| !range.is_empty()) {
+						let (start, end) = range.split_once('-').unwrap_or((range, range));
+						match (start.trim().parse(), end.trim().parse()) {
+							(Ok(start), Ok(end)) if start <= end => {
+								code_block_info.highlighted_lines.push((start, end))
+							}
+							(Ok(_), Ok(_)) => warnings.push(format!(
+								"Ignoring code block hl_lines range {range:?} which ends before it starts"
+							)),
+							_ => warnings.push(format!("Ignoring invalid code block hl_lines range {range:?}")),
+						}
+					}
+				}
+				_ => warnings.push(format!("Ignoring unknown code block option {token:?}")),
+			}
+		}
+		(code_block_info, warnings)
+	}
+
+	fn is_highlighted(&self, line_number: usize) -> bool {
+		self.highlighted_lines.iter().any(|(start, end)| (*start..=*end).contains(&line_number))
+	}
+}
+
+/// Returns the opening `<span>` tag that syntect generates for the given scope.
+fn scope_span(scope: &Scope) -> String {
+	let classes = scope.build_string().split('.').map(|atom| format!("{CLASS_PREFIX}{atom}")).join(" ");
+	format!("<span class=\"{classes}\">")
+}
+
+/// Formats a color as a CSS hex color value. The alpha channel is ignored.
+fn css_color(color: Color) -> String {
+	format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Returns the opening `<span>` tag for a line of code which is numbered and/or highlighted, including the line
+/// number itself. If a theme is given, the line highlight color from the theme is used as an inline style.
+fn line_start(line_number: usize, info: &CodeBlockInfo, theme: Option<&Theme>) -> String {
+	let mut html = if info.is_highlighted(line_number) {
+		let classes = format!("{CLASS_PREFIX}code-line {CLASS_PREFIX}code-line-highlighted");
+		match theme.and_then(|theme| theme.settings.line_highlight) {
+			Some(color) => format!("<span class=\"{classes}\" style=\"background-color:{};\">", css_color(color)),
+			None => format!("<span class=\"{classes}\">"),
+		}
+	} else {
+		format!("<span class=\"{CLASS_PREFIX}code-line\">")
+	};
+	if info.line_numbers {
+		html.push_str(&format!("<span class=\"{CLASS_PREFIX}code-line-number\">{line_number}</span>"));
+	}
+	html
 }
 
 struct SyntectContext {
 	syntax_set: SyntaxSet,
+	theme: Option<Theme>,
+	/// The CSS for the theme, for use with code highlighted using CSS classes.
+	theme_css: Option<String>,
+	/// Whether code is highlighted using inline styles from the theme, instead of CSS classes.
+	inline_styles: bool,
+	/// Maps lowercased code block languages to the language that should be used to find their syntax instead.
+	language_aliases: HashMap<String, String>,
+	/// Whether the syntax of code blocks without a language is detected from their first line.
+	detect_languages: bool,
+}
+
+/// Markdown events with all of the code blocks replaced by syntax highlighted HTML, along with the code block
+/// languages and options that had problems, for [`RenderedFormat`].
+struct HighlightedEvents<'input> {
+	events: Vec<Event<'input>>,
+	unknown_languages: Vec<String>,
+	warnings: Vec<String>,
 }
 
 pub struct MarkdownRenderer {
 	syntect_context: SyntectContext,
+	summary_paragraphs: usize,
+	heading_anchors: bool,
 }
 
 impl MarkdownRenderer {
@@ -37,40 +233,202 @@ impl MarkdownRenderer {
 		let syntax_names: Vec<&String> =
 			syntax_set.syntaxes().iter().flat_map(|syntax| &syntax.file_extensions).sorted().collect();
 		log::debug!("Syntaxes loaded: {:?}", syntax_names);
-		Ok(MarkdownRenderer { syntect_context: SyntectContext { syntax_set } })
+
+		let theme = match &server_config.syntax_theme {
+			None => None,
+			Some(theme_path) if theme_path.ends_with(".tmTheme") => {
+				log::debug!("Using syntax theme file: {:?}", theme_path);
+				Some(ThemeSet::get_theme(theme_path)?)
+			}
+			Some(theme_name) => {
+				log::debug!("Using built-in syntax theme: {:?}", theme_name);
+				let mut themes = ThemeSet::load_defaults().themes;
+				Some(themes.remove(theme_name).ok_or_else(|| MarkdownError::UnknownThemeError(theme_name.clone()))?)
+			}
+		};
+		let theme_css = match &theme {
+			Some(theme) => {
+				let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)?;
+				// syntect does not generate any css for highlighted lines, as they are specific to pbe
+				if let Some(color) = theme.settings.line_highlight {
+					css.push_str(&format!(
+						".{CLASS_PREFIX}code-line-highlighted {{\n background-color: {};\n}}\n",
+						css_color(color)
+					));
+				}
+				Some(css)
+			}
+			None => None,
+		};
+		if server_config.syntax_inline_styles && theme.is_none() {
+			log::warn!("syntax_inline_styles has no effect unless a syntax_theme is also set");
+		}
+
+		let mut language_aliases: HashMap<String, String> = DEFAULT_LANGUAGE_ALIASES
+			.iter()
+			.map(|(alias, language)| (alias.to_string(), language.to_string()))
+			.collect();
+		for (alias, language) in server_config.syntax_aliases.iter() {
+			if syntax_set.find_syntax_by_token(language).is_none() {
+				log::warn!("Syntax alias {:?} is for language {:?}, which has no matching syntax", alias, language);
+			}
+			language_aliases.insert(alias.to_lowercase(), language.clone());
+		}
+
+		Ok(MarkdownRenderer {
+			syntect_context: SyntectContext {
+				syntax_set,
+				theme,
+				theme_css,
+				inline_styles: server_config.syntax_inline_styles,
+				language_aliases,
+				detect_languages: server_config.detect_code_languages,
+			},
+			summary_paragraphs: server_config.summary_paragraphs.unwrap_or(1),
+			heading_anchors: server_config.heading_anchors,
+		})
 	}
 
-	fn highlight_code(&self, code: &str, language: &str) -> Result<String, MarkdownError> {
-		let syntax = self
-			.syntect_context
-			.syntax_set
-			.find_syntax_by_extension(language)
-			.unwrap_or_else(|| self.syntect_context.syntax_set.find_syntax_plain_text());
+	/// Returns the CSS for the configured syntax theme, or `None` if no theme is configured.
+	pub fn theme_css(&self) -> Option<&str> {
+		self.syntect_context.theme_css.as_deref()
+	}
 
-		let mut html_generator = ClassedHTMLGenerator::new_with_class_style(
-			syntax,
-			&self.syntect_context.syntax_set,
-			ClassStyle::SpacedPrefixed { prefix: "sh-" },
-		);
-		for line in LinesWithEndings::from(code) {
-			html_generator.parse_html_for_line_which_includes_newline(line)?;
+	/// Returns the theme to highlight code with using inline styles, if that is turned on.
+	fn inline_styles_theme(&self) -> Option<&Theme> {
+		self.syntect_context.theme.as_ref().filter(|_| self.syntect_context.inline_styles)
+	}
+
+	/// Finds the syntax for a code block language, which is matched against the file extensions and then the names of
+	/// the syntaxes, after first resolving any alias for it.
+	fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+		let language =
+			self.syntect_context.language_aliases.get(&language.to_lowercase()).map_or(language, String::as_str);
+		self.syntect_context.syntax_set.find_syntax_by_token(language)
+	}
+
+	/// Detects the syntax of a code block without a language from its first line, e.g. a shebang line, if language
+	/// detection is turned on.
+	fn detect_syntax(&self, code: &str) -> Option<&SyntaxReference> {
+		if !self.syntect_context.detect_languages {
+			return None;
 		}
+		let first_line = code.lines().next()?;
+		self.syntect_context.syntax_set.find_syntax_by_first_line(first_line)
+	}
+
+	fn highlight_code(&self, code: &str, info: &CodeBlockInfo) -> Result<String, MarkdownError> {
+		let syntax = if info.language.is_empty() { self.detect_syntax(code) } else { self.find_syntax(&info.language) };
+		let syntax = syntax.unwrap_or_else(|| self.syntect_context.syntax_set.find_syntax_plain_text());
+
+		let html = if let Some(theme) = self.inline_styles_theme() {
+			self.highlight_lines_with_inline_styles(code, syntax, theme, info)?
+		} else if info.line_numbers || !info.highlighted_lines.is_empty() {
+			self.highlight_lines(code, syntax, info)?
+		} else {
+			let mut html_generator =
+				ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntect_context.syntax_set, CLASS_STYLE);
+			for line in LinesWithEndings::from(code) {
+				html_generator.parse_html_for_line_which_includes_newline(line)?;
+			}
+			html_generator.finalize()
+		};
+
 		// the "sh-code" css class is what syntect will generate for the top-level code container that includes
 		// things like the background color and default text foreground color.
 		// the inner classname we're generating with the language included in it is not used for anything. it's
 		// just a marker that includes the name of the language syntax used
-		Ok(format!(
-			"<pre class=\"sh-code\"><code class=\"{}\">{}</code></pre>",
-			if !language.is_empty() { format!("syntax-{}", language) } else { String::new() },
-			html_generator.finalize()
-		))
+		let mut code_class = String::new();
+		if !info.language.is_empty() {
+			code_class.push_str("syntax-");
+			escape_html(&mut code_class, &info.language).unwrap();
+		}
+		let mut pre_style = String::new();
+		if let Some(theme) = self.inline_styles_theme() {
+			pre_style.push_str(" style=\"");
+			if let Some(background) = theme.settings.background {
+				pre_style.push_str(&format!("background-color:{};", css_color(background)));
+			}
+			if let Some(foreground) = theme.settings.foreground {
+				pre_style.push_str(&format!("color:{};", css_color(foreground)));
+			}
+			pre_style.push('"');
+		}
+		let pre =
+			format!("<pre class=\"{CLASS_PREFIX}code\"{pre_style}><code class=\"{code_class}\">{html}</code></pre>");
+		match &info.title {
+			None => Ok(pre),
+			Some(title) => {
+				let mut block = format!(
+					"<figure class=\"{CLASS_PREFIX}code-block\"><figcaption class=\"{CLASS_PREFIX}code-title\">"
+				);
+				escape_html(&mut block, title).unwrap();
+				block.push_str(&format!("</figcaption>{}</figure>", pre));
+				Ok(block)
+			}
+		}
 	}
 
+	/// Highlights the code using inline styles from the given theme instead of CSS classes. Each line is only wrapped
+	/// in its own `<span>` if it needs to be numbered and/or highlighted, like [`Self::highlight_lines`].
+	fn highlight_lines_with_inline_styles(
+		&self,
+		code: &str,
+		syntax: &SyntaxReference,
+		theme: &Theme,
+		info: &CodeBlockInfo,
+	) -> Result<String, MarkdownError> {
+		let is_wrapping_lines = info.line_numbers || !info.highlighted_lines.is_empty();
+		let background = IncludeBackground::IfDifferent(theme.settings.background.unwrap_or(Color::WHITE));
+		let mut highlighter = HighlightLines::new(syntax, theme);
+		let mut html = String::new();
+		for (index, line) in LinesWithEndings::from(code).enumerate() {
+			let regions = highlighter.highlight_line(line, &self.syntect_context.syntax_set)?;
+			if is_wrapping_lines {
+				html.push_str(&line_start(index + 1, info, Some(theme)));
+			}
+			html.push_str(&styled_line_to_highlighted_html(&regions, background)?);
+			if is_wrapping_lines {
+				html.push_str("</span>");
+			}
+		}
+		Ok(html)
+	}
+
+	/// Highlights the code one line at a time, wrapping each line in its own `<span>` so that it can be numbered
+	/// and/or highlighted. Syntax spans which are still open at the end of a line are closed there, and then opened
+	/// again at the start of the next line, so that the line spans are always properly nested.
+	fn highlight_lines(
+		&self,
+		code: &str,
+		syntax: &SyntaxReference,
+		info: &CodeBlockInfo,
+	) -> Result<String, MarkdownError> {
+		let mut parse_state = ParseState::new(syntax);
+		let mut scope_stack = ScopeStack::new();
+		let mut html = String::new();
+		for (index, line) in LinesWithEndings::from(code).enumerate() {
+			html.push_str(&line_start(index + 1, info, None));
+			for scope in scope_stack.as_slice() {
+				html.push_str(&scope_span(scope));
+			}
+			let ops = parse_state.parse_line(line, &self.syntect_context.syntax_set).map_err(syntect::Error::from)?;
+			let (line_html, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack)?;
+			html.push_str(&line_html);
+			html.push_str(&"</span>".repeat(scope_stack.as_slice().len()));
+			html.push_str("</span>");
+		}
+		Ok(html)
+	}
+
+	/// Replaces all code blocks with syntax highlighted HTML.
 	fn highlight_codeblocks<'input>(
 		&self,
 		events: Parser<'input, '_>,
-	) -> Result<impl Iterator<Item = Event<'input>> + 'input, MarkdownError> {
+	) -> Result<HighlightedEvents<'input>, MarkdownError> {
 		let mut modified_events = Vec::new();
+		let mut unknown_languages = Vec::new();
+		let mut warnings = Vec::new();
 		let mut code_buffer = String::new();
 		let mut is_in_code_block = false;
 
@@ -82,12 +440,17 @@ impl MarkdownRenderer {
 				}
 				Event::End(Tag::CodeBlock(kind)) => {
 					if is_in_code_block {
-						let language = if let CodeBlockKind::Fenced(language) = kind {
-							language.to_string()
+						let info = if let CodeBlockKind::Fenced(info) = kind {
+							let (info, mut info_warnings) = CodeBlockInfo::parse(&info);
+							warnings.append(&mut info_warnings);
+							info
 						} else {
-							String::new()
+							CodeBlockInfo::default()
 						};
-						let html = self.highlight_code(&code_buffer, &language)?;
+						if !info.language.is_empty() && self.find_syntax(&info.language).is_none() {
+							unknown_languages.push(info.language.clone());
+						}
+						let html = self.highlight_code(&code_buffer, &info)?;
 						modified_events.push(Event::Html(CowStr::Boxed(html.into())));
 						is_in_code_block = false;
 					}
@@ -103,16 +466,366 @@ impl MarkdownRenderer {
 			}
 		}
 
-		Ok(modified_events.into_iter())
+		Ok(HighlightedEvents { events: modified_events, unknown_languages, warnings })
+	}
+
+	/// Gives every heading an id so that it can be linked to, returning the modified events along with a table of
+	/// contents listing the headings. Ids given explicitly in the Markdown (e.g. `## Heading {#my-id}`) are kept as-is,
+	/// otherwise the id is generated from the heading text, with a number added on to the end of it if the same id
+	/// is used by any other heading. Explicit ids are reserved up front, so that a generated id never takes an
+	/// explicit id, even one given to a later heading. If heading anchors are turned on, a link to the heading itself
+	/// is also added to each heading.
+	fn add_heading_ids<'input>(&self, events: Vec<Event<'input>>) -> (Vec<Event<'input>>, Vec<TocEntry>) {
+		let mut modified_events = Vec::new();
+		let mut toc_entries = Vec::new();
+		let mut used_ids: HashSet<String> = events
+			.iter()
+			.filter_map(|event| match event {
+				Event::Start(Tag::Heading(_, Some(id), _)) => Some(id.to_string()),
+				_ => None,
+			})
+			.collect();
+		// the heading start tag is written out only once the end of the heading is reached and its text is known
+		let mut heading_start = None;
+		let mut heading_events = Vec::new();
+		let mut title = String::new();
+
+		for event in events {
+			match event {
+				Event::Start(Tag::Heading(level, id, classes)) => {
+					heading_start = Some((level, id, classes));
+					heading_events.clear();
+					title.clear();
+				}
+				Event::End(Tag::Heading(..)) if heading_start.is_some() => {
+					let (level, id, classes) = heading_start.take().unwrap();
+					let id = match id {
+						Some(id) => id.to_string(),
+						None => {
+							let base_id = slugify(&title);
+							let id = std::iter::once(base_id.clone())
+								.chain((1..).map(|n| format!("{base_id}-{n}")))
+								.find(|id| !used_ids.contains(id))
+								.unwrap();
+							used_ids.insert(id.clone());
+							id
+						}
+					};
+
+					let mut start_tag = format!("<{level} id=\"");
+					escape_html(&mut start_tag, &id).unwrap();
+					start_tag.push('"');
+					if !classes.is_empty() {
+						start_tag.push_str(" class=\"");
+						escape_html(&mut start_tag, &classes.join(" ")).unwrap();
+						start_tag.push('"');
+					}
+					start_tag.push('>');
+					modified_events.push(Event::Html(CowStr::Boxed(start_tag.into())));
+					modified_events.append(&mut heading_events);
+					if self.heading_anchors {
+						let mut anchor = String::from("<a class=\"heading-anchor\" href=\"#");
+						escape_html(&mut anchor, &id).unwrap();
+						anchor.push_str("\" aria-hidden=\"true\">#</a>");
+						modified_events.push(Event::Html(CowStr::Boxed(anchor.into())));
+					}
+					modified_events.push(Event::Html(CowStr::Boxed(format!("</{level}>\n").into())));
+
+					let level = level as usize;
+					toc_entries.push(TocEntry { level, title: title.trim().to_string(), id, children: Vec::new() });
+				}
+				event if heading_start.is_some() => {
+					if let Event::Text(text) | Event::Code(text) = &event {
+						title.push_str(text);
+					}
+					heading_events.push(event);
+				}
+				event => modified_events.push(event),
+			}
+		}
+
+		let toc = nest_toc_entries(&mut toc_entries.into_iter().peekable(), 0);
+		(modified_events, toc)
+	}
+
+	/// Returns the index of the event just past the end of the summary. This is either the end of the top-level block
+	/// containing a [`MORE_MARKER`], or if there is no marker, the end of the first `summary_paragraphs` top-level
+	/// paragraphs. Only ever splitting at the top-level ensures that the summary never ends with unclosed tags.
+	/// Returns `None` if the summary should include all the events.
+	fn find_summary_end(&self, events: &[Event]) -> Option<usize> {
+		let mut depth = 0;
+		let mut is_marker_found = false;
+		for (index, event) in events.iter().enumerate() {
+			match event {
+				Event::Start(_) => depth += 1,
+				Event::End(_) => depth -= 1,
+				Event::Html(html) if is_more_marker(html) => {
+					if depth == 0 {
+						return Some(index);
+					}
+					is_marker_found = true;
+				}
+				_ => {}
+			}
+			if is_marker_found && depth == 0 {
+				return Some(index + 1);
+			}
+		}
+
+		if self.summary_paragraphs == 0 {
+			return None;
+		}
+		let mut paragraphs = 0;
+		for (index, event) in events.iter().enumerate() {
+			match event {
+				Event::Start(_) => depth += 1,
+				Event::End(Tag::Paragraph) if depth == 1 => {
+					depth -= 1;
+					paragraphs += 1;
+					if paragraphs == self.summary_paragraphs {
+						return Some(index + 1);
+					}
+				}
+				Event::End(_) => depth -= 1,
+				_ => {}
+			}
+		}
+		None
 	}
 
-	pub fn render_to_html(&self, s: &str) -> Result<String, MarkdownError> {
+	pub fn render_to_html(&self, s: &str) -> Result<RenderedFormat, MarkdownError> {
 		let mut options = pulldown_cmark::Options::all();
 		options.set(pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION, false);
 		let parser = Parser::new_ext(s, options);
-		let events = self.highlight_codeblocks(parser)?;
-		let mut output = String::new();
-		pulldown_cmark::html::push_html(&mut output, events);
-		Ok(output)
+		let HighlightedEvents { events, unknown_languages, warnings } = self.highlight_codeblocks(parser)?;
+
+		// the summary is rendered before headings are given ids, as the summary is shown on pages (e.g. the archive,
+		// or in feeds) where the ids could clash with those of other summaries, and where anchors would lead nowhere
+		let mut summary_html = String::new();
+		if let Some(summary_end) = self.find_summary_end(&events) {
+			pulldown_cmark::html::push_html(
+				&mut summary_html,
+				events[..summary_end].iter().cloned().map(without_heading_id),
+			);
+		}
+		if summary_html.is_empty() {
+			pulldown_cmark::html::push_html(&mut summary_html, events.iter().cloned().map(without_heading_id));
+		}
+
+		let (events, toc) = self.add_heading_ids(events);
+		let mut html = String::new();
+		pulldown_cmark::html::push_html(&mut html, events.into_iter());
+		Ok(RenderedFormat { html, summary_html, unknown_languages, warnings, toc })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn renderer(extra_config: &str) -> MarkdownRenderer {
+		MarkdownRenderer::new(&config::tests::server_config(extra_config)).unwrap()
+	}
+
+	fn summary(renderer: &MarkdownRenderer, markdown: &str) -> String {
+		renderer.render_to_html(markdown).unwrap().summary_html
+	}
+
+	#[test]
+	fn summary_ends_at_a_more_marker() {
+		let renderer = renderer("");
+		assert_eq!(summary(&renderer, "one\n\ntwo\n\n<!-- more -->\n\nthree"), "<p>one</p>\n<p>two</p>\n");
+		assert_eq!(summary(&renderer, "one\n\n<!--MORE-->\n\ntwo"), "<p>one</p>\n");
+	}
+
+	#[test]
+	fn summary_ends_after_the_top_level_block_containing_a_more_marker() {
+		let renderer = renderer("");
+		assert_eq!(
+			summary(&renderer, "* one\n* two <!-- more -->\n* three\n\nafter"),
+			"<ul>\n<li>one</li>\n<li>two <!-- more --></li>\n<li>three</li>\n</ul>\n"
+		);
+	}
+
+	#[test]
+	fn summary_is_the_first_paragraphs_without_a_more_marker() {
+		assert_eq!(summary(&renderer(""), "one\n\ntwo\n\nthree"), "<p>one</p>\n");
+		assert_eq!(summary(&renderer("summary_paragraphs: 2"), "one\n\ntwo\n\nthree"), "<p>one</p>\n<p>two</p>\n");
+		assert_eq!(summary(&renderer("summary_paragraphs: 5"), "one\n\ntwo"), "<p>one</p>\n<p>two</p>\n");
+	}
+
+	#[test]
+	fn summary_paragraphs_only_count_top_level_paragraphs() {
+		assert_eq!(
+			summary(&renderer(""), "> quoted\n>\n> more quoted\n\none\n\ntwo"),
+			"<blockquote>\n<p>quoted</p>\n<p>more quoted</p>\n</blockquote>\n<p>one</p>\n"
+		);
+	}
+
+	#[test]
+	fn summary_is_everything_with_zero_summary_paragraphs() {
+		assert_eq!(summary(&renderer("summary_paragraphs: 0"), "one\n\ntwo"), "<p>one</p>\n<p>two</p>\n");
+	}
+
+	#[test]
+	fn summary_is_everything_without_any_paragraphs() {
+		assert_eq!(summary(&renderer(""), "* one\n* two"), "<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n");
+		assert_eq!(summary(&renderer(""), ""), "");
+	}
+
+	/// Describes a table of contents as e.g. `a(b c(d)) e`, listing each entry by its id followed by its children.
+	fn describe_toc(entries: &[TocEntry]) -> String {
+		entries
+			.iter()
+			.map(|entry| match entry.children.is_empty() {
+				true => entry.id.clone(),
+				false => format!("{}({})", entry.id, describe_toc(&entry.children)),
+			})
+			.join(" ")
+	}
+
+	fn html(renderer: &MarkdownRenderer, markdown: &str) -> String {
+		renderer.render_to_html(markdown).unwrap().html
+	}
+
+	#[test]
+	fn slugify_keeps_only_letters_and_numbers_separated_by_dashes() {
+		assert_eq!(slugify("Hello, World!"), "hello-world");
+		assert_eq!(slugify("  Some   spaced_out -- text  "), "some-spaced-out-text");
+		assert_eq!(slugify("Version 2.0"), "version-20");
+		assert_eq!(slugify("Ünïcödé Straße"), "ünïcödé-straße");
+		assert_eq!(slugify("-leading and trailing-"), "leading-and-trailing");
+	}
+
+	#[test]
+	fn slugify_falls_back_to_a_generic_id() {
+		assert_eq!(slugify(""), "section");
+		assert_eq!(slugify("!?"), "section");
+	}
+
+	#[test]
+	fn toc_entries_are_nested_under_shallower_headings() {
+		let rendered = renderer("").render_to_html("# a\n## b\n## c\n### d\n# e\n### f\n## g").unwrap();
+		assert_eq!(describe_toc(&rendered.toc), "a(b c(d)) e(f g)");
+	}
+
+	#[test]
+	fn toc_entries_start_at_the_shallowest_heading_level() {
+		let rendered = renderer("").render_to_html("### a\n#### b\n## c\n### d").unwrap();
+		assert_eq!(describe_toc(&rendered.toc), "a(b) c(d)");
+		assert_eq!(rendered.toc[0].level, 3);
+		assert_eq!(rendered.toc[0].title, "a");
+	}
+
+	#[test]
+	fn heading_ids_are_generated_from_the_heading_text() {
+		assert_eq!(
+			html(&renderer(""), "## Hello `code` *World*"),
+			"<h2 id=\"hello-code-world\">Hello <code>code</code> <em>World</em></h2>\n"
+		);
+	}
+
+	#[test]
+	fn duplicate_generated_heading_ids_are_numbered() {
+		let rendered = renderer("").render_to_html("# a\n# a\n# a\n# a-1").unwrap();
+		assert_eq!(describe_toc(&rendered.toc), "a a-1 a-2 a-1-1");
+	}
+
+	#[test]
+	fn explicit_heading_ids_are_kept_and_never_taken_by_generated_ids() {
+		let rendered = renderer("").render_to_html("# a\n# a\n# b {#a-1}\n# c {#a}").unwrap();
+		assert_eq!(describe_toc(&rendered.toc), "a-2 a-3 a-1 a");
+	}
+
+	#[test]
+	fn heading_anchors_link_to_their_heading() {
+		assert_eq!(
+			html(&renderer("heading_anchors: true"), "## Hi {#there}"),
+			"<h2 id=\"there\">Hi<a class=\"heading-anchor\" href=\"#there\" aria-hidden=\"true\">#</a></h2>\n"
+		);
+	}
+
+	#[test]
+	fn summary_headings_have_no_ids_or_anchors() {
+		let renderer = renderer("heading_anchors: true");
+		assert_eq!(summary(&renderer, "## One\n\ntext\n\n## Two"), "<h2>One</h2>\n<p>text</p>\n");
+		assert_eq!(
+			summary(&renderer, "## One {#one .big}\n\n* text"),
+			"<h2 class=\"big\">One</h2>\n<ul>\n<li>text</li>\n</ul>\n"
+		);
+	}
+
+	#[test]
+	fn info_strings_are_split_on_whitespace_outside_of_quotes() {
+		assert_eq!(split_info_string("  rust   linenos "), ["rust", "linenos"]);
+		assert_eq!(split_info_string("rust title=\"my  file.rs\" linenos"), ["rust", "title=my  file.rs", "linenos"]);
+		assert_eq!(split_info_string("hl_lines=\"1 3-5\""), ["hl_lines=1 3-5"]);
+		assert_eq!(split_info_string("title=\"unclosed quote"), ["title=unclosed quote"]);
+		assert!(split_info_string("").is_empty());
+	}
+
+	#[test]
+	fn code_block_info_is_parsed() {
+		let (info, warnings) = CodeBlockInfo::parse("rust title=\"src/main.rs\" linenos hl_lines=\"1 3-5,8\"");
+		assert_eq!(info.language, "rust");
+		assert_eq!(info.title.as_deref(), Some("src/main.rs"));
+		assert!(info.line_numbers);
+		assert_eq!(info.highlighted_lines, [(1, 1), (3, 5), (8, 8)]);
+		assert!(warnings.is_empty());
+		assert!(info.is_highlighted(4));
+		assert!(!info.is_highlighted(6));
+	}
+
+	#[test]
+	fn code_block_info_options_do_not_need_a_language() {
+		let (info, warnings) = CodeBlockInfo::parse("linenos title=\"Output\"");
+		assert_eq!(info.language, "");
+		assert!(info.line_numbers);
+		assert_eq!(info.title.as_deref(), Some("Output"));
+		assert!(warnings.is_empty());
+	}
+
+	#[test]
+	fn invalid_code_block_options_are_ignored_with_warnings() {
+		let (info, warnings) = CodeBlockInfo::parse("rust bogus hl_lines=\"5-3 x 2-4 7-\"");
+		assert_eq!(info.highlighted_lines, [(2, 4)]);
+		assert_eq!(
+			warnings,
+			[
+				"Ignoring unknown code block option \"bogus\"",
+				"Ignoring code block hl_lines range \"5-3\" which ends before it starts",
+				"Ignoring invalid code block hl_lines range \"x\"",
+				"Ignoring invalid code block hl_lines range \"7-\"",
+			]
+		);
+	}
+
+	#[test]
+	fn code_block_warnings_are_returned_with_the_rendered_markdown() {
+		let rendered = renderer("").render_to_html("```rust hl_lines=\"3-1\"\nfn main() {}\n```").unwrap();
+		assert_eq!(rendered.warnings, ["Ignoring code block hl_lines range \"3-1\" which ends before it starts"]);
+	}
+
+	#[test]
+	fn theme_css_styles_highlighted_lines() {
+		let renderer = renderer("syntax_theme: InspiredGitHub");
+		let css = renderer.theme_css().unwrap();
+		assert!(css.ends_with(".sh-code-line-highlighted {\n background-color: #f5f5f5;\n}\n"));
+	}
+
+	#[test]
+	fn code_block_lines_are_wrapped_when_numbered_or_highlighted() {
+		assert_eq!(
+			html(&renderer(""), "```text title=\"a & b\" linenos hl_lines=\"2\"\none\ntwo\n```"),
+			concat!(
+				"<figure class=\"sh-code-block\"><figcaption class=\"sh-code-title\">a &amp; b</figcaption>",
+				"<pre class=\"sh-code\"><code class=\"syntax-text\">",
+				"<span class=\"sh-code-line\"><span class=\"sh-code-line-number\">1</span>",
+				"<span class=\"sh-text sh-plain\">one\n</span></span>",
+				"<span class=\"sh-code-line sh-code-line-highlighted\"><span class=\"sh-code-line-number\">2</span>",
+				"<span class=\"sh-text sh-plain\">two\n</span></span>",
+				"</code></pre></figure>"
+			)
+		);
 	}
 }
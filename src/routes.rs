@@ -1,12 +1,19 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::middleware::ErrorHandlerResponse;
 use actix_web::web::Redirect;
 use actix_web::{web, Either, HttpRequest, HttpResponse, Responder};
 
+use crate::feeds::FeedFormat;
 use crate::site;
 
 fn not_found() -> HttpResponse {
 	HttpResponse::NotFound().body("not found")
 }
 
+fn first_page_redirect(url: String) -> Redirect {
+	Redirect::to(url).using_status_code(actix_web::http::StatusCode::MOVED_PERMANENTLY)
+}
+
 #[actix_web::route("/", method = "GET", method = "HEAD")]
 pub async fn latest_posts(data: web::Data<site::SiteService>) -> impl Responder {
 	log::debug!("GET / -> latest_posts()");
@@ -14,22 +21,103 @@ pub async fn latest_posts(data: web::Data<site::SiteService>) -> impl Responder
 }
 
 #[actix_web::route("/tag/{tag}", method = "GET", method = "HEAD")]
-pub async fn latest_posts_by_tag(path: web::Path<(String,)>, data: web::Data<site::SiteService>) -> impl Responder {
+pub async fn latest_posts_by_tag(
+	path: web::Path<(String,)>,
+	data: web::Data<site::SiteService>,
+) -> Result<HttpResponse, site::SiteError> {
 	let tag = path.into_inner().0;
 	log::debug!("GET /tag/{0} -> latest_posts_by_tag(), tag = {0}", tag);
-	data.serve_posts_by_tag(&tag)
+	Ok(data.serve_posts_by_tag(&tag, 1)?.unwrap_or_else(not_found))
+}
+
+#[actix_web::route("/tag/{tag}/page/{page}", method = "GET", method = "HEAD")]
+pub async fn latest_posts_by_tag_page(
+	path: web::Path<(String, usize)>,
+	data: web::Data<site::SiteService>,
+) -> Result<Either<HttpResponse, Redirect>, site::SiteError> {
+	let (tag, page) = path.into_inner();
+	log::debug!("GET /tag/{0}/page/{1} -> latest_posts_by_tag_page(), tag = {0}, page = {1}", tag, page);
+	// the first page is only served at the tag's url itself, so that the same page is not at two different urls
+	if page == 1 {
+		return Ok(Either::Right(first_page_redirect(site::tag_url(&tag))));
+	}
+	Ok(Either::Left(data.serve_posts_by_tag(&tag, page)?.unwrap_or_else(not_found)))
 }
 
 #[actix_web::route("/archive", method = "GET", method = "HEAD")]
-pub async fn posts_archive(data: web::Data<site::SiteService>) -> impl Responder {
+pub async fn posts_archive(data: web::Data<site::SiteService>) -> Result<HttpResponse, site::SiteError> {
 	log::debug!("GET /archive -> posts_archive()");
-	data.serve_posts_archive()
+	Ok(data.serve_posts_archive(1)?.unwrap_or_else(not_found))
+}
+
+#[actix_web::route("/archive/page/{page}", method = "GET", method = "HEAD")]
+pub async fn posts_archive_page(
+	path: web::Path<(usize,)>,
+	data: web::Data<site::SiteService>,
+) -> Result<Either<HttpResponse, Redirect>, site::SiteError> {
+	let page = path.into_inner().0;
+	log::debug!("GET /archive/page/{0} -> posts_archive_page(), page = {0}", page);
+	// the first page is only served at /archive itself, so that the same page is not at two different urls
+	if page == 1 {
+		return Ok(Either::Right(first_page_redirect(String::from("/archive"))));
+	}
+	Ok(Either::Left(data.serve_posts_archive(page)?.unwrap_or_else(not_found)))
 }
 
 #[actix_web::route("/rss", method = "GET", method = "HEAD")]
 pub async fn rss_feed(data: web::Data<site::SiteService>) -> impl Responder {
 	log::debug!("GET /rss -> rss_feed()");
-	data.serve_rss_feed()
+	data.serve_feed(FeedFormat::Rss, None)
+}
+
+#[actix_web::route("/tag/{tag}/rss", method = "GET", method = "HEAD")]
+pub async fn tag_rss_feed(path: web::Path<(String,)>, data: web::Data<site::SiteService>) -> impl Responder {
+	let tag = path.into_inner().0;
+	log::debug!("GET /tag/{0}/rss -> tag_rss_feed(), tag = {0}", tag);
+	data.serve_feed(FeedFormat::Rss, Some(&tag))
+}
+
+#[actix_web::route("/sitemap.xml", method = "GET", method = "HEAD")]
+pub async fn sitemap(data: web::Data<site::SiteService>) -> impl Responder {
+	log::debug!("GET /sitemap.xml -> sitemap()");
+	data.serve_sitemap()
+}
+
+#[actix_web::route("/__pbe/status", method = "GET", method = "HEAD")]
+pub async fn refresh_status(req: HttpRequest, data: web::Data<site::SiteService>) -> impl Responder {
+	log::debug!("GET /__pbe/status -> refresh_status()");
+	data.serve_refresh_status(&req).unwrap_or_else(not_found)
+}
+
+// only registered in dev mode
+#[actix_web::get("/__pbe/live-reload")]
+pub async fn live_reload(data: web::Data<site::SiteService>) -> impl Responder {
+	log::debug!("GET /__pbe/live-reload -> live_reload()");
+	data.serve_live_reload_events()
+}
+
+// the feed routes below are not registered via attributes as their paths are configurable
+
+pub async fn atom_feed(data: web::Data<site::SiteService>) -> impl Responder {
+	log::debug!("GET {} -> atom_feed()", data.server_config().feeds.atom_path);
+	data.serve_feed(FeedFormat::Atom, None)
+}
+
+pub async fn tag_atom_feed(path: web::Path<(String,)>, data: web::Data<site::SiteService>) -> impl Responder {
+	let tag = path.into_inner().0;
+	log::debug!("GET /tag/{0}{1} -> tag_atom_feed(), tag = {0}", tag, data.server_config().feeds.atom_path);
+	data.serve_feed(FeedFormat::Atom, Some(&tag))
+}
+
+pub async fn json_feed(data: web::Data<site::SiteService>) -> impl Responder {
+	log::debug!("GET {} -> json_feed()", data.server_config().feeds.json_feed_path);
+	data.serve_feed(FeedFormat::Json, None)
+}
+
+pub async fn tag_json_feed(path: web::Path<(String,)>, data: web::Data<site::SiteService>) -> impl Responder {
+	let tag = path.into_inner().0;
+	log::debug!("GET /tag/{0}{1} -> tag_json_feed(), tag = {0}", tag, data.server_config().feeds.json_feed_path);
+	data.serve_feed(FeedFormat::Json, Some(&tag))
 }
 
 pub async fn site_content(
@@ -37,9 +125,42 @@ pub async fn site_content(
 	data: web::Data<site::SiteService>,
 ) -> Result<Either<HttpResponse, Redirect>, site::SiteError> {
 	log::debug!("GET {} -> fallback to site_content()", req.path());
+	// robots.txt and syntax.css are served from here rather than their own routes so that a static file, if there is
+	// one, takes priority
+	if data.server_config().generate_robots_txt && req.path() == site::ROBOTS_TXT_PATH {
+		return Ok(Either::Left(data.serve_robots_txt()?));
+	}
+	if req.path() == site::SYNTAX_CSS_PATH {
+		if let Some(response) = data.serve_syntax_css() {
+			return Ok(Either::Left(response));
+		}
+	}
 	if let Some(response) = data.serve_content_by_url(&req)? {
 		Ok(response)
 	} else {
 		Ok(Either::Left(not_found()))
 	}
 }
+
+fn replace_response<B>(res: ServiceResponse<B>, response: HttpResponse) -> ErrorHandlerResponse<B> {
+	let (req, _) = res.into_parts();
+	ErrorHandlerResponse::Response(ServiceResponse::new(req, response).map_into_right_body())
+}
+
+/// Error handler which replaces any 404 response with the site's `404.html` template, if it has one.
+pub fn not_found_page<B>(res: ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+	let response = res.request().app_data::<web::Data<site::SiteService>>().and_then(|data| data.serve_not_found());
+	match response {
+		Some(response) => Ok(replace_response(res, response)),
+		None => Ok(ErrorHandlerResponse::Response(res.map_into_left_body())),
+	}
+}
+
+/// Error handler which replaces any 5xx response with the site's `error.html` template, if it has one.
+pub fn error_page<B>(res: ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+	let Some(data) = res.request().app_data::<web::Data<site::SiteService>>().cloned() else {
+		return Ok(ErrorHandlerResponse::Response(res.map_into_left_body()));
+	};
+	let response = data.serve_error(res.status(), res.response().error());
+	Ok(replace_response(res, response))
+}
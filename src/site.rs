@@ -1,21 +1,58 @@
-use std::collections::HashMap;
-use std::ops::Deref;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use actix_web::body::BoxBody;
-use actix_web::http::header::ContentType;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
 use actix_web::http::StatusCode;
 use actix_web::web::Redirect;
 use actix_web::{Either, HttpRequest, HttpResponse};
-use chrono::{Datelike, TimeZone};
+use arc_swap::ArcSwap;
+use chrono::Datelike;
 use itertools::Itertools;
 
-use crate::{config, markdown};
+use crate::feeds::FeedFormat;
+use crate::live_reload::{LiveReload, ReloadEvent};
+use crate::sitemap::SitemapUrl;
+use crate::status::RefreshStatus;
+use crate::util::{
+	current_datetime, decode_path, describe_error_chain, drop_trailing_slash, encode_path_segment, find_link_urls,
+	html_to_text, insert_before_body_end, localize_datetime, lock_cache, parse_post_file_path, truncate_text,
+};
+use crate::{config, feeds, formats, live_reload, markdown, sitemap};
 
 type UriPath = String;
 type Tag = String;
 
+/// Returns the url path of the first page of posts with the given tag.
+pub fn tag_url(tag: &str) -> UriPath {
+	format!("/tag/{}", encode_path_segment(tag))
+}
+
+/// The `tag_url` filter for templates, e.g. `{{ tag | tag_url }}`, so that templates link to tags using exactly the
+/// same urls as [`tag_url`] does.
+struct TagUrlFilter;
+
+impl tera::Filter for TagUrlFilter {
+	fn filter(&self, value: &tera::Value, _args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+		match value.as_str() {
+			Some(tag) => Ok(tera::Value::String(tag_url(tag))),
+			None => {
+				Err(tera::Error::msg(format!("Filter `tag_url` was used on a value that is not a string: {value}")))
+			}
+		}
+	}
+
+	// percent-encoding leaves nothing in the url that would need to be escaped in HTML
+	fn is_safe(&self) -> bool {
+		true
+	}
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, thiserror::Error)]
 pub enum ContentError {
 	#[error("Content rendering I/O error with path {0}")]
@@ -26,36 +63,158 @@ pub enum ContentError {
 
 	#[error("Markdown rendering error with path {0}")]
 	MarkdownRenderingError(PathBuf, #[source] markdown::MarkdownError),
+
+	#[error("Front matter deserialization error with path {0}")]
+	FrontMatterError(PathBuf, #[source] serde_yaml::Error),
+
+	#[error("No {1} was specified for content with path {0}")]
+	MissingFieldError(PathBuf, &'static str),
+
+	#[error("Content format {1} for file extension {0} does not exist")]
+	UnknownFormatError(String, String),
+}
+
+/// Splits an optional YAML front matter block, delimited by `---` lines, off of the top of the raw content. Returns
+/// the front matter YAML and the remaining content.
+fn split_front_matter(raw_content: &str) -> Option<(&str, &str)> {
+	let rest = raw_content.strip_prefix("---\n").or_else(|| raw_content.strip_prefix("---\r\n"))?;
+	let mut offset = 0;
+	for line in rest.split_inclusive('\n') {
+		if line.trim_end() == "---" {
+			return Some((&rest[..offset], &rest[offset + line.len()..]));
+		}
+		offset += line.len();
+	}
+	None
+}
+
+#[derive(Clone)]
+pub struct RenderedContent {
+	pub output: formats::RenderedFormat,
+	pub front_matter: config::FrontMatter,
+	/// The modification time of the content file, if the filesystem provides one.
+	pub modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+struct CachedRender {
+	hash: u64,
+	rendered: RenderedContent,
 }
 
 pub struct ContentRenderer {
-	markdown_renderer: markdown::MarkdownRenderer,
+	markdown_renderer: Arc<markdown::MarkdownRenderer>,
+	formats: formats::ContentFormats,
+	cache: Mutex<HashMap<PathBuf, CachedRender>>,
 }
 
 impl ContentRenderer {
 	pub fn new(server_config: &config::Server) -> Result<Self, ContentError> {
-		Ok(ContentRenderer {
-			//
-			markdown_renderer: markdown::MarkdownRenderer::new(server_config)?,
-		})
+		Self::with_formats(server_config, |_| {})
+	}
+
+	/// Creates a renderer which can also render content formats beyond the built-in ones, which are registered with
+	/// the [`formats::ContentFormats`] passed to `register_formats`. This happens before the file extensions given in
+	/// `content_formats` in `server.yml` are mapped to formats, so that they can be mapped to these formats too.
+	pub fn with_formats(
+		server_config: &config::Server,
+		register_formats: impl FnOnce(&mut formats::ContentFormats),
+	) -> Result<Self, ContentError> {
+		let markdown_renderer = Arc::new(markdown::MarkdownRenderer::new(server_config)?);
+		let mut formats = formats::ContentFormats::new(markdown_renderer.clone());
+		register_formats(&mut formats);
+		formats.map_configured_extensions(server_config)?;
+		Ok(ContentRenderer { formats, markdown_renderer, cache: Mutex::new(HashMap::new()) })
 	}
 
-	pub fn render(&self, path: &PathBuf) -> Result<String, ContentError> {
+	/// Renders the content file at the given path. The result is cached, keyed by both the path and a hash of the
+	/// file's contents, so that content which has not changed since it was last rendered is not rendered again. If
+	/// `changed_paths` is given, it holds every content file known to have changed (e.g. as reported by the filesystem
+	/// watcher), and a cached render of any other file is used as-is without even reading the file again.
+	pub fn render(
+		&self,
+		path: &PathBuf,
+		changed_paths: Option<&HashSet<PathBuf>>,
+	) -> Result<RenderedContent, ContentError> {
+		if changed_paths.is_some_and(|changed_paths| !changed_paths.contains(path)) {
+			if let Some(cached) = lock_cache(&self.cache).get(path) {
+				log::debug!("Using cached render of unchanged {:?}", path);
+				return Ok(cached.rendered.clone());
+			}
+		}
+
 		let raw_content = match std::fs::read_to_string(path) {
 			Err(e) => return Err(ContentError::IOError(path.clone(), e)),
 			Ok(s) => s,
 		};
-		match path.extension().unwrap_or_default().to_str() {
-			Some("md") => match self.markdown_renderer.render_to_html(&raw_content) {
-				Err(e) => return Err(ContentError::MarkdownRenderingError(path.clone(), e)),
-				Ok(output) => Ok(output),
+		let modified =
+			std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok().map(chrono::DateTime::from);
+
+		let mut hasher = DefaultHasher::new();
+		raw_content.hash(&mut hasher);
+		let hash = hasher.finish();
+
+		if let Some(cached) = lock_cache(&self.cache).get(path) {
+			if cached.hash == hash {
+				log::debug!("Using cached render of {:?}", path);
+				return Ok(RenderedContent { modified, ..cached.rendered.clone() });
+			}
+		}
+
+		log::debug!("Rendering {:?}", path);
+		let rendered = self.render_uncached(path, &raw_content, modified)?;
+		lock_cache(&self.cache).insert(path.clone(), CachedRender { hash, rendered: rendered.clone() });
+		Ok(rendered)
+	}
+
+	fn render_uncached(
+		&self,
+		path: &Path,
+		raw_content: &str,
+		modified: Option<chrono::DateTime<chrono::Utc>>,
+	) -> Result<RenderedContent, ContentError> {
+		let (front_matter, raw_content) = match split_front_matter(raw_content) {
+			Some((yaml, rest)) if yaml.trim().is_empty() => (config::FrontMatter::default(), rest),
+			Some((yaml, rest)) => match serde_yaml::from_str(yaml) {
+				Err(e) => return Err(ContentError::FrontMatterError(path.to_path_buf(), e)),
+				Ok(front_matter) => (front_matter, rest),
 			},
-			Some("html") | Some("htm") => Ok(raw_content),
-			_ => Ok(raw_content),
+			None => (config::FrontMatter::default(), raw_content),
+		};
+		let output = self.formats.renderer_for(path).render(path, raw_content)?;
+		for language in output.unknown_languages.iter().unique() {
+			log::warn!(
+				"Code block language {:?} in {:?} has no matching syntax, so it was not highlighted",
+				language,
+				path
+			);
+		}
+		for warning in output.warnings.iter() {
+			log::warn!("{} in {:?}", warning, path);
 		}
+		Ok(RenderedContent { output, front_matter, modified })
+	}
+
+	pub fn syntax_css(&self) -> Option<&str> {
+		self.markdown_renderer.theme_css()
+	}
+
+	pub fn clear_cache(&self) {
+		lock_cache(&self.cache).clear();
+	}
+
+	/// Removes cached renders of any content files that are no longer part of the site.
+	pub fn prune_cache(&self, content: &SiteContent) {
+		let file_paths = content
+			.pages
+			.iter()
+			.map(|page| &page.file_path)
+			.chain(content.posts.iter().map(|post| &post.file_path))
+			.collect::<HashSet<&PathBuf>>();
+		lock_cache(&self.cache).retain(|path, _| file_paths.contains(path));
 	}
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, thiserror::Error)]
 pub enum SiteError {
 	#[error("Content rendering error")]
@@ -63,18 +222,36 @@ pub enum SiteError {
 
 	#[error("Tera templates error")]
 	TeraError(#[from] tera::Error),
+
+	#[error("Url {0} is used by both {1} and {2}")]
+	DuplicateUrlError(UriPath, PathBuf, PathBuf),
+
+	#[error("Alternate url {0} of {1} is the url of {2}")]
+	AlternateUrlCollisionError(UriPath, PathBuf, PathBuf),
+
+	#[error("Alternate url {0} of {1} is its own url, so it would redirect to itself")]
+	AlternateUrlIsOwnUrlError(UriPath, PathBuf),
+
+	#[error("{} url collision(s) found:\n{}", .0.len(), .0.iter().map(|error| format!("  {error}")).join("\n"))]
+	UrlCollisionsError(Vec<SiteError>),
+
+	#[error("The rss url {0:?} in posts.yml is not a valid absolute url")]
+	InvalidSiteUrlError(String, #[source] url::ParseError),
 }
 
 impl actix_web::error::ResponseError for SiteError {
 	fn error_response(&self) -> HttpResponse<BoxBody> {
 		log::error!("Error response: {:?}", self);
+		// the details of the error are intentionally left out, as they can include things like filesystem paths. the
+		// error page handler will show them instead, but only if dev mode is turned on
 		let status_code = self.status_code();
 		HttpResponse::build(status_code) //
 			.content_type(ContentType::plaintext())
-			.body(format!("{status_code}\n\n{:#?}", self))
+			.body(status_code.to_string())
 	}
 }
 
+#[derive(Clone)]
 pub struct AlternateUrlMappings {
 	mapping: HashMap<UriPath, UriPath>,
 }
@@ -99,8 +276,13 @@ impl AlternateUrlMappings {
 			self.add_mapping(url, current_url);
 		}
 	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&UriPath, &UriPath)> {
+		self.mapping.iter()
+	}
 }
 
+#[derive(Clone)]
 pub struct PostsByTag {
 	mapping: HashMap<Tag, Vec<usize>>,
 }
@@ -128,73 +310,282 @@ impl PostsByTag {
 			self.add_mapping(post_index, tag);
 		}
 	}
+
+	pub fn tags(&self) -> impl Iterator<Item = &Tag> {
+		self.mapping.keys()
+	}
 }
 
-#[derive(serde::Serialize)]
+/// Maximum length of the plain-text description generated from the summary of a post.
+const DESCRIPTION_MAX_LENGTH: usize = 200;
+
+pub const NOT_FOUND_TEMPLATE: &str = "404.html";
+pub const ERROR_TEMPLATE: &str = "error.html";
+
+pub const SITEMAP_PATH: &str = "/sitemap.xml";
+pub const ROBOTS_TXT_PATH: &str = "/robots.txt";
+pub const SYNTAX_CSS_PATH: &str = "/syntax.css";
+
+#[derive(Clone, serde::Serialize)]
 pub struct Post {
 	pub url: UriPath,
 	pub title: String,
 	pub content_html: String,
+	pub summary_html: String,
+	pub description: String,
 	#[serde(serialize_with = "crate::util::serialize_naivedatetime_to_i64")]
 	pub date: chrono::NaiveDateTime,
 	pub tags: Vec<Tag>,
+	pub draft: bool,
+	pub toc: Vec<markdown::TocEntry>,
+	#[serde(skip)]
+	pub alternate_urls: Vec<UriPath>,
+	#[serde(skip)]
+	pub modified: Option<chrono::DateTime<chrono::Utc>>,
+	#[serde(skip)]
+	pub file_path: PathBuf,
 }
 
 impl Post {
-	pub fn try_from(value: config::Post, content_renderer: &ContentRenderer) -> Result<Self, SiteError> {
+	pub fn try_from(
+		value: config::Post,
+		content_renderer: &ContentRenderer,
+		changed_paths: Option<&HashSet<PathBuf>>,
+	) -> Result<Self, SiteError> {
+		let rendered = content_renderer.render(&value.file_path, changed_paths)?;
+		let front_matter = rendered.front_matter;
+		let missing = |field| ContentError::MissingFieldError(value.file_path.clone(), field);
+
+		let (file_date, file_slug) = parse_post_file_path(&value.file_path).unzip();
+
+		let title = value.title.or(front_matter.title).ok_or_else(|| missing("title"))?;
+		let date = value.date.or(front_matter.date).or(file_date).ok_or_else(|| missing("date"))?;
+		let slug = value.slug.or(front_matter.slug).or(file_slug).ok_or_else(|| missing("slug"))?;
 		let url = format!(
 			"/{:04}/{:02}/{:02}/{}", //
-			value.date.year(),
-			value.date.month(),
-			value.date.day(),
-			value.slug
+			date.year(),
+			date.month(),
+			date.day(),
+			slug
 		);
-		let content_html = content_renderer.render(&value.file_path)?;
-		let tags = value.tags.map_or_else(|| Vec::new(), |x| x.clone());
+		let tags = value.tags.or(front_matter.tags).unwrap_or_default();
+		let draft = value.draft.or(front_matter.draft).unwrap_or_default();
+		let description = front_matter
+			.description
+			.unwrap_or_else(|| truncate_text(&html_to_text(&rendered.output.summary_html), DESCRIPTION_MAX_LENGTH));
+		let mut alternate_urls = value.alternate_urls.or(front_matter.alternate_urls).unwrap_or_default();
+		alternate_urls.iter_mut().for_each(drop_trailing_slash);
 		Ok(Post {
 			url, //
-			title: value.title,
-			content_html,
-			date: value.date,
+			title,
+			content_html: rendered.output.html,
+			summary_html: rendered.output.summary_html,
+			description,
+			date,
 			tags,
+			draft,
+			toc: rendered.output.toc,
+			alternate_urls,
+			modified: rendered.modified,
+			file_path: value.file_path,
 		})
 	}
+
+	/// Returns true if this post is visible to the public as of the given date/time. That is, it is not a draft and
+	/// it is not scheduled to be published at a later date/time.
+	pub fn is_published(&self, now: &chrono::NaiveDateTime) -> bool {
+		!self.draft && self.date <= *now
+	}
 }
 
-#[derive(serde::Serialize)]
+#[derive(Clone, serde::Serialize)]
 pub struct Page {
 	pub url: UriPath,
 	pub title: String,
 	pub content_html: String,
+	pub toc: Vec<markdown::TocEntry>,
+	#[serde(skip)]
+	pub alternate_urls: Vec<UriPath>,
+	#[serde(skip)]
+	pub modified: Option<chrono::DateTime<chrono::Utc>>,
+	#[serde(skip)]
+	pub file_path: PathBuf,
 }
 
 impl Page {
-	pub fn try_from(value: config::Page, content_renderer: &ContentRenderer) -> Result<Self, SiteError> {
-		let content_html = content_renderer.render(&value.file_path)?;
+	pub fn try_from(
+		value: config::Page,
+		content_renderer: &ContentRenderer,
+		changed_paths: Option<&HashSet<PathBuf>>,
+	) -> Result<Self, SiteError> {
+		let rendered = content_renderer.render(&value.file_path, changed_paths)?;
+		let front_matter = rendered.front_matter;
+		let missing = |field| ContentError::MissingFieldError(value.file_path.clone(), field);
+
+		let title = value.title.or(front_matter.title).ok_or_else(|| missing("title"))?;
+		let mut url = value.url.or(front_matter.url).or(value.default_url).ok_or_else(|| missing("url"))?;
+		drop_trailing_slash(&mut url);
+		let mut alternate_urls = value.alternate_urls.or(front_matter.alternate_urls).unwrap_or_default();
+		alternate_urls.iter_mut().for_each(drop_trailing_slash);
 		Ok(Page {
-			url: value.url, //
-			title: value.title,
-			content_html,
+			url, //
+			title,
+			content_html: rendered.output.html,
+			toc: rendered.output.toc,
+			alternate_urls,
+			modified: rendered.modified,
+			file_path: value.file_path,
 		})
 	}
 }
 
+/// Loads a post or page using `load`. If it is a discovered content file that has no title, it is skipped with a
+/// warning by returning `None` instead of failing, as files found under the content paths are not all necessarily
+/// meant to be published as they are.
+pub fn load_discoverable<T>(
+	file_path: &Path,
+	discovered: bool,
+	load: impl FnOnce() -> Result<T, SiteError>,
+) -> Result<Option<T>, SiteError> {
+	match load() {
+		Err(SiteError::ContentError(ContentError::MissingFieldError(_, "title"))) if discovered => {
+			log::warn!("Skipping discovered content file {:?} as it has no title", file_path);
+			Ok(None)
+		}
+		result => result.map(Some),
+	}
+}
+
+#[derive(Clone)]
 pub struct RssMetadata {
 	pub title: String,
 	pub description: String,
-	pub url: String,
+	/// The base url of the site, which all absolute urls (e.g. in feeds and the sitemap) are built from.
+	pub url: url::Url,
 	pub count: usize,
 }
 
-impl From<config::Rss> for RssMetadata {
-	fn from(value: config::Rss) -> Self {
-		RssMetadata {
-			title: value.title, //
+impl TryFrom<config::Rss> for RssMetadata {
+	type Error = SiteError;
+
+	fn try_from(value: config::Rss) -> Result<Self, Self::Error> {
+		Ok(RssMetadata {
+			url: parse_site_url(&value)?,
+			title: value.title,
 			description: value.description,
-			url: value.url,
 			count: value.count,
+		})
+	}
+}
+
+/// Parses the base url of the site, as configured under `rss` in `posts.yml`.
+pub fn parse_site_url(rss: &config::Rss) -> Result<url::Url, SiteError> {
+	url::Url::parse(&rss.url).map_err(|e| SiteError::InvalidSiteUrlError(rss.url.clone(), e))
+}
+
+#[derive(serde::Serialize)]
+pub struct Paginator {
+	pub current_page: usize,
+	pub total_pages: usize,
+	pub previous_url: Option<UriPath>,
+	pub next_url: Option<UriPath>,
+}
+
+impl Paginator {
+	pub fn page_url(base_url: &str, page: usize) -> UriPath {
+		if page == 1 {
+			base_url.to_string()
+		} else {
+			format!("{base_url}/page/{page}")
+		}
+	}
+
+	/// Returns the number of pages needed for the given number of items. There is always at least one page, even if
+	/// there are no items.
+	pub fn total_pages(count: usize, per_page: Option<NonZeroUsize>) -> usize {
+		per_page.map_or(1, |per_page| count.div_ceil(per_page.get()).max(1))
+	}
+
+	/// Returns the given page of items, along with a paginator for it. The first page is at `base_url` and any
+	/// following pages are at `{base_url}/page/{page}`. Returns `None` if the page is out of range. If `per_page` is
+	/// `None`, all items are returned on a single page.
+	pub fn paginate<'a, T>(
+		items: &'a [T],
+		page: usize,
+		per_page: Option<NonZeroUsize>,
+		base_url: &str,
+	) -> Option<(&'a [T], Paginator)> {
+		let total_pages = Self::total_pages(items.len(), per_page);
+		if page < 1 || page > total_pages {
+			return None;
+		}
+		let per_page = per_page.map_or(items.len(), NonZeroUsize::get);
+		let start = (page - 1) * per_page;
+		let end = (start + per_page).min(items.len());
+		let paginator = Paginator {
+			current_page: page,
+			total_pages,
+			previous_url: (page > 1).then(|| Self::page_url(base_url, page - 1)),
+			next_url: (page < total_pages).then(|| Self::page_url(base_url, page + 1)),
+		};
+		Some((&items[start..end], paginator))
+	}
+}
+
+/// Finds all of the urls which are used by more than one post or page, either as their url or as an alternate url, as
+/// well as any alternate urls which are the url of a post or page (including the one they belong to). Any of these
+/// would otherwise result in content silently being hidden by other content or by a redirect.
+pub fn find_url_collisions(pages: &[Page], posts: &[Post]) -> Vec<SiteError> {
+	let entries = pages
+		.iter()
+		.map(|page| (&page.url, &page.alternate_urls, &page.file_path))
+		.chain(posts.iter().map(|post| (&post.url, &post.alternate_urls, &post.file_path)))
+		.collect::<Vec<_>>();
+	let file_path = |index: usize| entries[index].2.clone();
+
+	// the first post or page to use a url is the one that keeps it, any others using it are in error
+	let mut errors = Vec::new();
+	let mut urls: HashMap<&UriPath, usize> = HashMap::new();
+	for (index, (url, _, _)) in entries.iter().enumerate() {
+		let existing = *urls.entry(url).or_insert(index);
+		if existing != index {
+			errors.push(SiteError::DuplicateUrlError(url.to_string(), file_path(existing), file_path(index)));
+		}
+	}
+	let mut alternate_urls: HashMap<&UriPath, usize> = HashMap::new();
+	for (index, (_, entry_alternate_urls, _)) in entries.iter().enumerate() {
+		for alternate_url in entry_alternate_urls.iter() {
+			if *entries[index].0 == *alternate_url {
+				errors.push(SiteError::AlternateUrlIsOwnUrlError(alternate_url.clone(), file_path(index)));
+			} else if let Some(existing) = urls.get(alternate_url) {
+				errors.push(SiteError::AlternateUrlCollisionError(
+					alternate_url.clone(),
+					file_path(index),
+					file_path(*existing),
+				));
+			}
+			let existing = *alternate_urls.entry(alternate_url).or_insert(index);
+			if existing != index {
+				errors.push(SiteError::DuplicateUrlError(alternate_url.clone(), file_path(existing), file_path(index)));
+			}
 		}
 	}
+	errors
+}
+
+struct CachedFeed {
+	body: String,
+	/// The date/time at which the next scheduled post is published, after which this feed must be rendered again.
+	valid_until: Option<chrono::NaiveDateTime>,
+}
+
+type FeedCacheKey = (FeedFormat, Option<Tag>);
+
+/// A root-relative link in the content of a post or page which does not lead anywhere on the site.
+pub struct BrokenLink {
+	/// The path of the content file the link is in.
+	pub file_path: PathBuf,
+	pub url: String,
 }
 
 pub enum Content<'a> {
@@ -204,7 +595,8 @@ pub enum Content<'a> {
 }
 
 pub struct SiteContent {
-	pub template_renderer: tera::Tera,
+	/// The templates are swappable on their own, so that they can be reloaded without copying all of the content.
+	pub template_renderer: ArcSwap<tera::Tera>,
 	pub pages: Vec<Page>,
 	pub posts: Vec<Post>,
 	pub pages_by_url: HashMap<UriPath, usize>,
@@ -212,61 +604,97 @@ pub struct SiteContent {
 	pub alternate_url_mappings: AlternateUrlMappings,
 	pub post_tag_mappings: PostsByTag,
 	pub rss: RssMetadata,
+	pub posts_per_page: Option<NonZeroUsize>,
+	pub homepage_posts: usize,
+	pub feeds: config::Feeds,
+	pub timezone: Option<chrono_tz::Tz>,
+	/// The server config and content renderer that this content was built with. These are kept together with the
+	/// content so that all three are always swapped in at the same time when the site is refreshed.
+	pub server_config: Arc<config::Server>,
+	pub content_renderer: Arc<ContentRenderer>,
+	/// The content configs that this content was built from.
+	pub content_configs: (config::Pages, config::Posts),
+	feed_cache: Mutex<HashMap<FeedCacheKey, CachedFeed>>,
 }
 
 impl SiteContent {
-	pub fn new(
-		pages_config: config::Pages,
-		posts_config: config::Posts,
-		server_config: &config::Server,
-		content_renderer: &ContentRenderer,
-	) -> Result<Self, SiteError> {
+	pub fn load_templates(server_config: &config::Server) -> Result<tera::Tera, SiteError> {
 		let mut templates_path = PathBuf::from(&server_config.templates_path);
 		templates_path.push("**/*");
 		log::debug!("Using templates path: {:?}", templates_path);
-		let template_renderer = tera::Tera::new(templates_path.as_path().to_str().unwrap())?;
+		let mut template_renderer = tera::Tera::new(templates_path.as_path().to_str().unwrap())?;
+		template_renderer.register_filter("tag_url", TagUrlFilter);
 		log::debug!(
 			"Templates loaded and parsed from the templates path: {:?}",
 			template_renderer.get_template_names().collect::<Vec<&str>>()
 		);
+		Ok(template_renderer)
+	}
 
+	pub fn new(
+		pages_config: config::Pages,
+		posts_config: config::Posts,
+		server_config: Arc<config::Server>,
+		content_renderer: Arc<ContentRenderer>,
+		template_renderer: Arc<tera::Tera>,
+		changed_paths: Option<&HashSet<PathBuf>>,
+	) -> Result<Self, SiteError> {
+		let content_configs = (pages_config.clone(), posts_config.clone());
 		let mut alternate_url_mappings = AlternateUrlMappings::new();
 		let mut post_tag_mappings = PostsByTag::new();
 
 		// load pages
 		let mut pages = Vec::new();
 		let mut pages_by_url = HashMap::new();
-		for (index, page_config) in pages_config.pages.iter().enumerate() {
-			let page = Page::try_from(page_config.clone(), content_renderer)?;
-
-			if let Some(old_urls) = &page_config.alternate_urls {
-				alternate_url_mappings.add_mappings(old_urls, &page.url);
-			}
-
-			pages_by_url.insert(page.url.clone(), index);
+		for page_config in pages_config.pages.into_iter() {
+			let (file_path, discovered) = (page_config.file_path.clone(), page_config.discovered);
+			let Some(page) = load_discoverable(&file_path, discovered, || {
+				Page::try_from(page_config, &content_renderer, changed_paths)
+			})?
+			else {
+				continue;
+			};
+			alternate_url_mappings.add_mappings(&page.alternate_urls, &page.url);
+			pages_by_url.insert(page.url.clone(), pages.len());
 			pages.push(page);
 		}
 
-		// load posts, iterating over the config's list of posts in descending order by date so that
-		// our final post list is pre-sorted this way, as well as the post lists per tag
+		// load posts. the date of each post is not known until its content has been rendered (as it may be specified
+		// in the content's front matter), so all posts are first rendered and only then sorted in descending order
+		// by date so that our final post list is pre-sorted this way, as well as the post lists per tag
 		let mut posts = Vec::new();
 		let mut posts_by_url = HashMap::new();
-		for (index, post_config) in posts_config.posts.iter().sorted_by(|a, b| b.date.cmp(&a.date)).enumerate() {
-			let post = Post::try_from(post_config.clone(), content_renderer)?;
-
-			if let Some(old_urls) = &post_config.alternate_urls {
-				alternate_url_mappings.add_mappings(old_urls, &post.url);
+		let mut rendered_posts = Vec::new();
+		for post_config in posts_config.posts.into_iter() {
+			let (file_path, discovered) = (post_config.file_path.clone(), post_config.discovered);
+			if let Some(post) = load_discoverable(&file_path, discovered, || {
+				Post::try_from(post_config, &content_renderer, changed_paths)
+			})? {
+				rendered_posts.push(post);
 			}
-
+		}
+		for (index, post) in rendered_posts.into_iter().sorted_by(|a, b| b.date.cmp(&a.date)).enumerate() {
+			alternate_url_mappings.add_mappings(&post.alternate_urls, &post.url);
 			posts_by_url.insert(post.url.clone(), index);
 			post_tag_mappings.add_mappings(index, &post.tags);
 			posts.push(post);
 		}
 
-		let rss = RssMetadata::from(posts_config.rss);
+		let url_collisions = find_url_collisions(&pages, &posts);
+		if !url_collisions.is_empty() {
+			return Err(SiteError::UrlCollisionsError(url_collisions));
+		}
+
+		let now = current_datetime(server_config.timezone.as_ref());
+		let unpublished_count = posts.iter().filter(|post| !post.is_published(&now)).count();
+		if unpublished_count > 0 {
+			log::info!("{} draft or scheduled post(s) will not be published yet", unpublished_count);
+		}
+
+		let rss = RssMetadata::try_from(posts_config.rss)?;
 
 		Ok(SiteContent {
-			template_renderer,
+			template_renderer: ArcSwap::new(template_renderer),
 			pages,
 			posts,
 			pages_by_url,
@@ -274,6 +702,14 @@ impl SiteContent {
 			alternate_url_mappings,
 			post_tag_mappings,
 			rss,
+			posts_per_page: server_config.posts_per_page,
+			homepage_posts: server_config.homepage_posts.unwrap_or(1),
+			feeds: server_config.feeds.clone(),
+			timezone: server_config.timezone,
+			server_config,
+			content_renderer,
+			content_configs,
+			feed_cache: Mutex::new(HashMap::new()),
 		})
 	}
 
@@ -285,60 +721,311 @@ impl SiteContent {
 		self.posts_by_url.get(url).map(|index| self.posts.get(*index).unwrap())
 	}
 
-	pub fn get_content_at(&self, url: &UriPath) -> Option<Content> {
+	/// Returns the content at the given url. Posts that are not yet published, and redirects to them, are only
+	/// returned if `include_unpublished` is true.
+	pub fn get_content_at(&self, url: &UriPath, include_unpublished: bool) -> Option<Content<'_>> {
+		let now = self.now();
+		let is_visible = |post: &Post| include_unpublished || post.is_published(&now);
 		if let Some(new_url) = self.alternate_url_mappings.get(url) {
-			Some(Content::Redirect(new_url.clone()))
+			match self.get_post_by_url(new_url) {
+				Some(post) if !is_visible(post) => None,
+				_ => Some(Content::Redirect(new_url.clone())),
+			}
 		} else if let Some(post) = self.get_post_by_url(url) {
-			Some(Content::Post(post))
-		} else if let Some(page) = self.get_page_by_url(url) {
-			Some(Content::Page(page))
+			is_visible(post).then_some(Content::Post(post))
 		} else {
-			None
+			self.get_page_by_url(url).map(Content::Page)
 		}
 	}
 
-	pub fn get_posts_ordered_by_date(&self) -> &[Post] {
-		self.posts.as_slice()
+	/// The current date/time that post publishing is checked against.
+	pub fn now(&self) -> chrono::NaiveDateTime {
+		current_datetime(self.timezone.as_ref())
+	}
+
+	/// Returns the date/time at which the next scheduled post will be published, if there are any.
+	pub fn get_next_publish_date(&self) -> Option<chrono::NaiveDateTime> {
+		let now = self.now();
+		self.posts.iter().filter(|post| !post.draft && post.date > now).map(|post| post.date).min()
+	}
+
+	pub fn get_posts_ordered_by_date(&self) -> Vec<&Post> {
+		let now = self.now();
+		self.posts.iter().filter(|post| post.is_published(&now)).collect()
 	}
 
 	pub fn get_posts_with_tag_ordered_by_date(&self, tag: &Tag) -> Vec<&Post> {
+		let now = self.now();
 		let mut posts = Vec::new();
 		if let Some(post_indices) = self.post_tag_mappings.get(tag) {
 			for post_index in post_indices.iter() {
-				posts.push(self.posts.get(*post_index).unwrap())
+				let post = self.posts.get(*post_index).unwrap();
+				if post.is_published(&now) {
+					posts.push(post)
+				}
 			}
 		}
 		posts
 	}
 
 	pub fn get_latest_post(&self) -> Option<&Post> {
-		self.posts.first()
+		let now = self.now();
+		self.posts.iter().find(|post| post.is_published(&now))
 	}
-}
 
-pub struct RefreshWrapper<T> {
-	pub data: T,
-}
+	pub fn get_latest_posts(&self, count: usize) -> Vec<&Post> {
+		let now = self.now();
+		self.posts.iter().filter(|post| post.is_published(&now)).take(count).collect()
+	}
 
-impl<T> RefreshWrapper<T> {
-	pub fn new(data: T) -> Self {
-		RefreshWrapper { data }
+	pub fn render_latest_post(&self) -> Result<String, SiteError> {
+		let post = self.get_latest_post();
+		let posts = self.get_latest_posts(self.homepage_posts);
+		let mut context = tera::Context::new();
+		if let Some(post) = post {
+			context.insert("post", post);
+		}
+		context.insert("posts", &posts);
+		Ok(self.template_renderer.load().render("latest_post.html", &context)?)
 	}
-}
 
-impl<T> Deref for RefreshWrapper<T> {
-	type Target = T;
+	/// Renders the given page of posts with the given tag. Returns `None` if the page is out of range.
+	pub fn render_posts_by_tag(&self, tag: &Tag, page: usize) -> Result<Option<String>, SiteError> {
+		let posts = self.get_posts_with_tag_ordered_by_date(tag);
+		let Some((posts, paginator)) = Paginator::paginate(&posts, page, self.posts_per_page, &tag_url(tag)) else {
+			return Ok(None);
+		};
+		let mut context = tera::Context::new();
+		context.insert("tag", tag);
+		context.insert("posts", &posts);
+		context.insert("paginator", &paginator);
+		Ok(Some(self.template_renderer.load().render("tag.html", &context)?))
+	}
 
-	#[inline]
-	fn deref(&self) -> &Self::Target {
-		&self.data
+	/// Renders the given page of the posts archive. Returns `None` if the page is out of range.
+	pub fn render_posts_archive(&self, page: usize) -> Result<Option<String>, SiteError> {
+		let posts = self.get_posts_ordered_by_date();
+		let Some((posts, paginator)) = Paginator::paginate(&posts, page, self.posts_per_page, "/archive") else {
+			return Ok(None);
+		};
+		let mut context = tera::Context::new();
+		context.insert("posts", &posts);
+		context.insert("paginator", &paginator);
+		Ok(Some(self.template_renderer.load().render("archive.html", &context)?))
+	}
+
+	/// Returns the url path that the feed in the given format is served at. If a tag is given, this is the path of
+	/// the feed for only the posts with that tag.
+	pub fn feed_path(&self, format: FeedFormat, tag: Option<&Tag>) -> String {
+		let path = match format {
+			FeedFormat::Rss => "/rss",
+			FeedFormat::Atom => &self.feeds.atom_path,
+			FeedFormat::Json => &self.feeds.json_feed_path,
+		};
+		match tag {
+			Some(tag) => format!("{}{}", tag_url(tag), path),
+			None => path.to_string(),
+		}
+	}
+
+	/// Returns the feed of the most recent posts in the given format. If a tag is given, the feed only includes the
+	/// posts with that tag. Rendered feeds are cached until the next scheduled post is published.
+	pub fn render_feed(&self, format: FeedFormat, tag: Option<&Tag>) -> String {
+		// only feeds for tags that actually exist are cached, otherwise the cache could grow without bound
+		if tag.is_some_and(|tag| self.post_tag_mappings.get(tag).is_none()) {
+			return self.build_feed(format, tag);
+		}
+
+		let mut feed_cache = lock_cache(&self.feed_cache);
+		let key = (format, tag.cloned());
+		let now = self.now();
+		if let Some(cached) = feed_cache.get(&key) {
+			if cached.valid_until.is_none_or(|valid_until| now < valid_until) {
+				return cached.body.clone();
+			}
+		}
+
+		log::debug!("Rendering {:?} feed for tag {:?}", format, tag);
+		let body = self.build_feed(format, tag);
+		feed_cache.insert(key, CachedFeed { body: body.clone(), valid_until: self.get_next_publish_date() });
+		body
+	}
+
+	fn build_feed(&self, format: FeedFormat, tag: Option<&Tag>) -> String {
+		let (title, posts) = match tag {
+			Some(tag) => (format!("{} :: {}", self.rss.title, tag), self.get_posts_with_tag_ordered_by_date(tag)),
+			None => (self.rss.title.clone(), self.get_posts_ordered_by_date()),
+		};
+		let feed_path = self.feed_path(format, tag);
+		let feed = feeds::Feed {
+			title: &title,
+			description: &self.rss.description,
+			site_url: &self.rss.url,
+			feed_path: &feed_path,
+			posts: &posts[..posts.len().min(self.rss.count)],
+			full_content: self.feeds.full_content,
+			timezone: self.timezone.as_ref(),
+		};
+		feed.render(format)
+	}
+
+	fn absolute_url(&self, path: &str) -> String {
+		self.rss.url.join(path).map_or_else(|_| path.to_string(), |url| url.to_string())
+	}
+
+	/// Renders an XML sitemap listing the canonical url of all published content, as well as the homepage, archive
+	/// and tag pages. Alternate urls are left out, as they only redirect to the canonical urls.
+	pub fn render_sitemap(&self) -> String {
+		let posts = self.get_posts_ordered_by_date();
+		let latest_date =
+			|posts: &[&Post]| posts.first().map(|post| localize_datetime(&post.date, self.timezone.as_ref()));
+
+		let mut urls = vec![
+			SitemapUrl { loc: self.absolute_url("/"), lastmod: latest_date(&posts) },
+			SitemapUrl { loc: self.absolute_url("/archive"), lastmod: latest_date(&posts) },
+		];
+		for tag in self.post_tag_mappings.tags().sorted() {
+			let tag_posts = self.get_posts_with_tag_ordered_by_date(tag);
+			if !tag_posts.is_empty() {
+				urls.push(SitemapUrl { loc: self.absolute_url(&tag_url(tag)), lastmod: latest_date(&tag_posts) });
+			}
+		}
+		for post in posts.iter() {
+			// a post file may have been edited after the post was published, so the later of the two is used
+			let date = localize_datetime(&post.date, self.timezone.as_ref());
+			let lastmod = post.modified.map_or(date, |modified| date.max(modified.fixed_offset()));
+			urls.push(SitemapUrl { loc: self.absolute_url(&post.url), lastmod: Some(lastmod) });
+		}
+		for page in self.pages.iter() {
+			urls.push(SitemapUrl {
+				loc: self.absolute_url(&page.url),
+				lastmod: page.modified.map(|modified| modified.fixed_offset()),
+			});
+		}
+		sitemap::render(&urls)
+	}
+
+	pub fn render_robots_txt(&self) -> String {
+		sitemap::render_robots_txt(&self.absolute_url(SITEMAP_PATH))
+	}
+
+	/// Returns all of the url paths served for this content, other than static files.
+	fn known_urls(&self) -> HashSet<UriPath> {
+		let formats = [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json];
+		let paginated_urls = |base_url: &str, count: usize| {
+			(1..=Paginator::total_pages(count, self.posts_per_page))
+				.map(|page| Paginator::page_url(base_url, page))
+				.collect::<Vec<UriPath>>()
+		};
+
+		let mut urls = HashSet::from([UriPath::from("/"), UriPath::from(SITEMAP_PATH)]);
+		if self.server_config.generate_robots_txt {
+			urls.insert(UriPath::from(ROBOTS_TXT_PATH));
+		}
+		if self.content_renderer.syntax_css().is_some() {
+			urls.insert(UriPath::from(SYNTAX_CSS_PATH));
+		}
+		urls.extend(formats.map(|format| self.feed_path(format, None)));
+		urls.extend(paginated_urls("/archive", self.get_posts_ordered_by_date().len()));
+		for tag in self.post_tag_mappings.tags() {
+			let base_url = tag_url(tag);
+			urls.extend(paginated_urls(&base_url, self.get_posts_with_tag_ordered_by_date(tag).len()));
+			urls.extend(formats.map(|format| self.feed_path(format, Some(tag))));
+		}
+		urls.extend(self.pages_by_url.keys().cloned());
+		urls.extend(self.posts_by_url.keys().cloned());
+		urls.extend(self.alternate_url_mappings.iter().map(|(alternate_url, _)| alternate_url.clone()));
+		urls
+	}
+
+	/// Finds all of the root-relative links (in `href` and `src` attributes) in the content of the posts and pages
+	/// which lead to neither a url served for this content, nor a file under the static files path. Links to posts
+	/// which are not published yet are not considered broken, as they will work once the post is published.
+	pub fn find_broken_links(&self) -> Vec<BrokenLink> {
+		// links may percent-encode characters that the known urls do not (or vice versa), so both are compared decoded
+		let known_urls: HashSet<String> = self.known_urls().iter().map(|url| decode_path(url)).collect();
+		let contents = self
+			.pages
+			.iter()
+			.map(|page| (&page.content_html, &page.file_path))
+			.chain(self.posts.iter().map(|post| (&post.content_html, &post.file_path)))
+			.unique_by(|(_, file_path)| *file_path);
+
+		let mut broken_links = Vec::new();
+		for (content_html, file_path) in contents {
+			for link in find_link_urls(content_html).into_iter().unique() {
+				// protocol-relative links (e.g. "//example.com/") are external links
+				if !link.starts_with('/') || link.starts_with("//") {
+					continue;
+				}
+				let path = decode_path(link.split(['?', '#']).next().unwrap_or_default());
+				let mut url = path.clone();
+				if url.len() > 1 {
+					drop_trailing_slash(&mut url);
+				}
+				let static_file_path = self.server_config.static_files_path.join(path.trim_start_matches('/'));
+				if !known_urls.contains(&url) && !static_file_path.is_file() {
+					broken_links.push(BrokenLink { file_path: file_path.clone(), url: link });
+				}
+			}
+		}
+		broken_links
+	}
+
+	/// Renders the given template, if there is one with that name. The templates are only loaded once for this, in
+	/// case they are swapped out in between checking for the template and rendering it.
+	fn render_optional_template(&self, name: &str, context: &tera::Context) -> Result<Option<String>, SiteError> {
+		let template_renderer = self.template_renderer.load();
+		if !template_renderer.get_template_names().any(|template| template == name) {
+			return Ok(None);
+		}
+		Ok(Some(template_renderer.render(name, context)?))
+	}
+
+	/// Renders the `404.html` template, if there is one.
+	pub fn render_not_found(&self) -> Result<Option<String>, SiteError> {
+		self.render_optional_template(NOT_FOUND_TEMPLATE, &tera::Context::new())
+	}
+
+	/// Renders the `error.html` template, if there is one. The details of the error that occurred should only be
+	/// provided when they are safe to show to visitors.
+	pub fn render_error(&self, status_code: StatusCode, details: Option<&str>) -> Result<Option<String>, SiteError> {
+		let mut context = tera::Context::new();
+		context.insert("status", &status_code.as_u16());
+		context.insert("reason", status_code.canonical_reason().unwrap_or_default());
+		context.insert("error", &details);
+		self.render_optional_template(ERROR_TEMPLATE, &context)
+	}
+
+	pub fn render_page(&self, page: &Page) -> Result<String, SiteError> {
+		let mut context = tera::Context::new();
+		context.insert("page", page);
+		Ok(self.template_renderer.load().render("page.html", &context)?)
+	}
+
+	pub fn render_post(&self, post: &Post) -> Result<String, SiteError> {
+		let mut context = tera::Context::new();
+		context.insert("post", post);
+		Ok(self.template_renderer.load().render("post.html", &context)?)
+	}
+}
+
+/// Returns true if the request's query string has a parameter with the given name whose value matches `key`. Always
+/// false if there is no key, so that a secret key left unset does not grant access to anything.
+fn has_query_key(req: &HttpRequest, name: &str, key: Option<&str>) -> bool {
+	match key {
+		Some(key) => url::form_urlencoded::parse(req.query_string().as_bytes())
+			.any(|(param, value)| param == name && value == key),
+		None => false,
 	}
 }
 
 pub struct SiteService {
-	pub server_config: config::Server,
-	pub content_renderer: ContentRenderer,
-	pub content: RwLock<RefreshWrapper<SiteContent>>,
+	/// The current site content. This is swapped out for entirely new content whenever the site is refreshed, so
+	/// anything serving a request always has a consistent view of the site without ever needing to wait on a lock.
+	pub content: ArcSwap<SiteContent>,
+	pub refresh_status: ArcSwap<RefreshStatus>,
+	pub live_reload: LiveReload,
 }
 
 impl SiteService {
@@ -347,102 +1034,288 @@ impl SiteService {
 		pages_config: config::Pages,
 		posts_config: config::Posts,
 	) -> Result<Self, SiteError> {
-		let content_renderer = ContentRenderer::new(&server_config)?;
-		let content =
-			RefreshWrapper::new(SiteContent::new(pages_config, posts_config, &server_config, &content_renderer)?);
+		let content = Self::build_content(server_config, pages_config, posts_config)?;
+		Self::warn_broken_links(&content);
 		Ok(SiteService {
-			server_config, //
-			content_renderer,
-			content: RwLock::new(content),
+			content: ArcSwap::from_pointee(content), //
+			refresh_status: ArcSwap::from_pointee(RefreshStatus::succeeded()),
+			live_reload: LiveReload::new(),
 		})
 	}
 
-	pub fn refresh_content(&self, pages_config: config::Pages, posts_config: config::Posts) -> Result<(), SiteError> {
-		let mut existing_content = self.content.write().expect("SiteContent write lock failed"); // TODO: better error handling
-		log::debug!("Obtained write lock on SiteContent instance");
-		let content = SiteContent::new(pages_config, posts_config, &self.server_config, &self.content_renderer)?;
+	/// Builds all site content from scratch, including a new content renderer.
+	fn build_content(
+		server_config: config::Server,
+		pages_config: config::Pages,
+		posts_config: config::Posts,
+	) -> Result<SiteContent, SiteError> {
+		let content_renderer = Arc::new(ContentRenderer::new(&server_config)?);
+		let template_renderer = Arc::new(SiteContent::load_templates(&server_config)?);
+		SiteContent::new(pages_config, posts_config, Arc::new(server_config), content_renderer, template_renderer, None)
+	}
+
+	/// The server config that the current site content was built with.
+	pub fn server_config(&self) -> Arc<config::Server> {
+		let content = self.content.load();
+		content.server_config.clone()
+	}
+
+	pub fn content_renderer(&self) -> Arc<ContentRenderer> {
+		let content = self.content.load();
+		content.content_renderer.clone()
+	}
+
+	/// Logs a warning for each broken link in the given content, if checking for them is turned on.
+	fn warn_broken_links(content: &SiteContent) {
+		if !content.server_config.warn_broken_links {
+			return;
+		}
+		for link in content.find_broken_links() {
+			log::warn!("Broken link to {} in {:?}", link.url, link.file_path);
+		}
+	}
+
+	fn replace_content(&self, content: SiteContent) {
+		Self::warn_broken_links(&content);
+		self.content.store(Arc::new(content));
+		log::debug!("Swapped in new SiteContent instance");
+	}
+
+	/// Rebuilds everything from scratch using the given server config, including the content renderer, so that
+	/// changes to things like the syntax definitions are picked up. Settings in the new server config which can only
+	/// take effect when the server is started keep their current values, and a warning is logged for them instead.
+	pub fn rebuild(
+		&self,
+		mut server_config: config::Server,
+		pages_config: config::Pages,
+		posts_config: config::Posts,
+	) -> Result<(), SiteError> {
+		let restart_required = server_config.keep_startup_settings(&self.server_config());
+		if !restart_required.is_empty() {
+			log::warn!("Changes to {:?} in server.yml will only take effect after PBE is restarted", restart_required);
+		}
+		let content = Self::build_content(server_config, pages_config, posts_config)?;
+		log::debug!("New SiteContent instance built successfully");
+		self.replace_content(content);
+		Ok(())
+	}
+
+	/// Returns the content configs that the current content was built from, if they can be re-used as-is after the
+	/// given content files changed. That is the case as long as they are all existing content files of the site, as
+	/// otherwise files were added or removed, which changes the content configs when content discovery is used.
+	pub fn reusable_content_configs(&self, changed_paths: &[PathBuf]) -> Option<(config::Pages, config::Posts)> {
+		let content = self.content.load();
+		let (pages_config, posts_config) = &content.content_configs;
+		let file_paths = pages_config
+			.pages
+			.iter()
+			.map(|page| &page.file_path)
+			.chain(posts_config.posts.iter().map(|post| &post.file_path))
+			.collect::<HashSet<&PathBuf>>();
+		let is_reusable = changed_paths.iter().all(|path| file_paths.contains(path) && path.is_file());
+		is_reusable.then(|| content.content_configs.clone())
+	}
+
+	/// Re-generates all site content from the given configs. Only content files which have changed since they were
+	/// last rendered are actually rendered again, unless the render cache is cleared first. If `changed_paths` is
+	/// given, only those content files are assumed to have changed, and the others are not even read again. The
+	/// existing templates are re-used unless `reload_templates` is true.
+	pub fn refresh_content(
+		&self,
+		pages_config: config::Pages,
+		posts_config: config::Posts,
+		reload_templates: bool,
+		changed_paths: Option<&[PathBuf]>,
+	) -> Result<(), SiteError> {
+		let (server_config, content_renderer, template_renderer) = {
+			let existing_content = self.content.load();
+			let template_renderer = if reload_templates {
+				Arc::new(SiteContent::load_templates(&existing_content.server_config)?)
+			} else {
+				existing_content.template_renderer.load_full()
+			};
+			(existing_content.server_config.clone(), existing_content.content_renderer.clone(), template_renderer)
+		};
+		let changed_paths = changed_paths.map(|changed_paths| changed_paths.iter().cloned().collect::<HashSet<_>>());
+		let content = SiteContent::new(
+			pages_config,
+			posts_config,
+			server_config,
+			content_renderer.clone(),
+			template_renderer,
+			changed_paths.as_ref(),
+		)?;
 		log::debug!("New SiteContent instance built successfully");
-		existing_content.data = content;
+		content_renderer.prune_cache(&content);
+		self.replace_content(content);
 		Ok(())
 	}
 
+	/// Re-loads only the templates, keeping all of the existing content as-is.
+	pub fn refresh_templates(&self) -> Result<(), SiteError> {
+		let content = self.content.load();
+		let template_renderer = SiteContent::load_templates(&content.server_config)?;
+		content.template_renderer.store(Arc::new(template_renderer));
+		log::debug!("Swapped in new templates");
+		Ok(())
+	}
+
+	/// Records the outcome of an attempt at refreshing the site content, and lets any connected browsers know about it
+	/// if live reload is being used.
+	pub fn refresh_finished(&self, result: Result<(), &(dyn std::error::Error + 'static)>) {
+		match result {
+			Ok(()) => {
+				self.refresh_status.store(Arc::new(RefreshStatus::succeeded()));
+				self.live_reload.notify(ReloadEvent::Reloaded);
+			}
+			Err(error) => {
+				let status = RefreshStatus::failed(&self.refresh_status.load(), error);
+				self.refresh_status.store(Arc::new(status));
+				self.live_reload.notify(ReloadEvent::Failed(describe_error_chain(error)));
+			}
+		}
+	}
+
+	/// Returns an HTML response, which has the dev mode extras added to it if `server_config` (which should be that of
+	/// the content the body was rendered from) has dev mode turned on.
+	fn html_response(&self, server_config: &config::Server, status_code: StatusCode, mut body: String) -> HttpResponse {
+		if server_config.dev_mode {
+			if let Some(banner) = self.refresh_status.load().render_banner() {
+				insert_before_body_end(&mut body, &banner);
+			}
+			live_reload::inject_script(&mut body);
+		}
+		HttpResponse::build(status_code).content_type(ContentType::html()).body(body)
+	}
+
+	pub fn serve_live_reload_events(&self) -> HttpResponse {
+		HttpResponse::Ok()
+			.content_type("text/event-stream")
+			.insert_header(CacheControl(vec![CacheDirective::NoCache]))
+			.streaming(self.live_reload.event_stream())
+	}
+
 	pub fn serve_latest_post(&self) -> Result<HttpResponse, SiteError> {
-		let content = self.content.read().expect("SiteContent read lock failed"); // TODO: better error handling
-		let post = content.get_latest_post();
-		let mut context = tera::Context::new();
-		if let Some(post) = post {
-			context.insert("post", post);
+		let content = self.content.load();
+		let response_body = content.render_latest_post()?;
+		Ok(self.html_response(&content.server_config, StatusCode::OK, response_body))
+	}
+
+	pub fn serve_posts_by_tag(&self, tag: &Tag, page: usize) -> Result<Option<HttpResponse>, SiteError> {
+		let content = self.content.load();
+		let response_body = content.render_posts_by_tag(tag, page)?;
+		Ok(response_body.map(|body| self.html_response(&content.server_config, StatusCode::OK, body)))
+	}
+
+	pub fn serve_posts_archive(&self, page: usize) -> Result<Option<HttpResponse>, SiteError> {
+		let content = self.content.load();
+		let response_body = content.render_posts_archive(page)?;
+		Ok(response_body.map(|body| self.html_response(&content.server_config, StatusCode::OK, body)))
+	}
+
+	pub fn serve_feed(&self, format: FeedFormat, tag: Option<&Tag>) -> Result<HttpResponse, SiteError> {
+		let content = self.content.load();
+		let response_body = content.render_feed(format, tag);
+		Ok(HttpResponse::Ok().content_type(format.content_type()).body(response_body))
+	}
+
+	pub fn serve_sitemap(&self) -> Result<HttpResponse, SiteError> {
+		let content = self.content.load();
+		let response_body = content.render_sitemap();
+		Ok(HttpResponse::Ok().content_type(ContentType::xml()).body(response_body))
+	}
+
+	pub fn serve_robots_txt(&self) -> Result<HttpResponse, SiteError> {
+		let content = self.content.load();
+		let response_body = content.render_robots_txt();
+		Ok(HttpResponse::Ok().content_type(ContentType::plaintext()).body(response_body))
+	}
+
+	/// Responds with the generated syntax highlighting CSS as `text/css`. Returns `None` if no syntax theme is
+	/// configured, in which case the request is handled the same as for any other url.
+	pub fn serve_syntax_css(&self) -> Option<HttpResponse> {
+		let content = self.content.load();
+		let response_body = content.content_renderer.syntax_css()?.to_string();
+		Some(HttpResponse::Ok().content_type("text/css").body(response_body))
+	}
+
+	/// Returns a response rendered from the `404.html` template, or `None` if there is no such template.
+	pub fn serve_not_found(&self) -> Option<HttpResponse> {
+		let content = self.content.load();
+		match content.render_not_found() {
+			Ok(response_body) => {
+				response_body.map(|body| self.html_response(&content.server_config, StatusCode::NOT_FOUND, body))
+			}
+			Err(err) => {
+				log::error!("Error rendering not found page: {:?}", err);
+				None
+			}
 		}
-		let response_body = content.template_renderer.render("latest_post.html", &context)?;
-		Ok(HttpResponse::Ok().content_type(ContentType::html()).body(response_body))
 	}
 
-	pub fn serve_posts_by_tag(&self, tag: &Tag) -> Result<HttpResponse, SiteError> {
-		let content = self.content.read().expect("SiteContent read lock failed"); // TODO: better error handling
-		let posts = content.get_posts_with_tag_ordered_by_date(tag);
-		let mut context = tera::Context::new();
-		context.insert("tag", tag);
-		context.insert("posts", &posts);
-		let response_body = content.template_renderer.render("tag.html", &context)?;
-		Ok(HttpResponse::Ok().content_type(ContentType::html()).body(response_body))
+	/// Returns a response for the given server error, rendered from the `error.html` template if there is one. The
+	/// details of the error are only included when dev mode is turned on.
+	pub fn serve_error(&self, status_code: StatusCode, error: Option<&actix_web::Error>) -> HttpResponse {
+		let content = self.content.load();
+		let details = match error {
+			Some(error) if content.server_config.dev_mode => Some(match error.as_error::<SiteError>() {
+				Some(error) => describe_error_chain(error),
+				None => error.to_string(),
+			}),
+			_ => None,
+		};
+		match content.render_error(status_code, details.as_deref()) {
+			Ok(Some(body)) => {
+				return self.html_response(&content.server_config, status_code, body);
+			}
+			Ok(None) => {}
+			Err(err) => log::error!("Error rendering error page: {:?}", err),
+		}
+		let body = match details {
+			Some(details) => format!("{status_code}\n\n{details}"),
+			None => status_code.to_string(),
+		};
+		HttpResponse::build(status_code).content_type(ContentType::plaintext()).body(body)
 	}
 
-	pub fn serve_posts_archive(&self) -> Result<HttpResponse, SiteError> {
-		let content = self.content.read().expect("SiteContent read lock failed"); // TODO: better error handling
-		let posts = content.get_posts_ordered_by_date();
-		let mut context = tera::Context::new();
-		context.insert("posts", &posts);
-		let response_body = content.template_renderer.render("archive.html", &context)?;
-		Ok(HttpResponse::Ok().content_type(ContentType::html()).body(response_body))
-	}
-
-	pub fn serve_rss_feed(&self) -> Result<HttpResponse, SiteError> {
-		let content = self.content.read().expect("SiteContent read lock failed"); // TODO: better error handling
-		let base_url = url::Url::parse(&content.rss.url).unwrap();
-		let posts = content.get_posts_ordered_by_date();
-		let mut channel = rss::ChannelBuilder::default() //
-			.title(&content.rss.title)
-			.description(&content.rss.description)
-			.link(&content.rss.url)
-			.build();
-		channel.set_items(
-			posts
-				.iter()
-				.take(content.rss.count)
-				.map(|post| {
-					rss::ItemBuilder::default() //
-						.title(post.title.clone())
-						.content(post.content_html.clone())
-						.link(base_url.clone().join(&post.url).unwrap().to_string())
-						.pub_date(chrono::Local.from_local_datetime(&post.date).unwrap().to_string())
-						.build()
-				})
-				.collect::<Vec<rss::Item>>(),
-		);
-		let response_body = channel.to_string();
-		Ok(HttpResponse::Ok().content_type("application/rss+xml").body(response_body))
+	/// Returns the status of the most recent refresh as JSON. This is only available in dev mode, or when the
+	/// `status_key` from the server config is given as the `key` query parameter.
+	pub fn serve_refresh_status(&self, req: &HttpRequest) -> Option<HttpResponse> {
+		let server_config = self.server_config();
+		if !server_config.dev_mode && !has_query_key(req, "key", server_config.status_key.as_deref()) {
+			return None;
+		}
+		let response_body = serde_json::to_string_pretty(self.refresh_status.load().as_ref())
+			.expect("RefreshStatus serialization failed");
+		Some(
+			HttpResponse::Ok()
+				.content_type(ContentType::json())
+				.insert_header(CacheControl(vec![CacheDirective::NoStore]))
+				.body(response_body),
+		)
 	}
 
 	pub fn serve_content_by_url(&self, req: &HttpRequest) -> Result<Option<Either<HttpResponse, Redirect>>, SiteError> {
-		let content = self.content.read().expect("SiteContent read lock failed"); // TODO: better error handling
+		let content = self.content.load();
 		let url = String::from(req.path());
-		match content.get_content_at(&url) {
+		let is_preview = has_query_key(req, "preview", content.server_config.preview_key.as_deref());
+		match content.get_content_at(&url, is_preview) {
 			Some(Content::Page(page)) => {
 				log::debug!("Found page content at {}", req.path());
-				let mut context = tera::Context::new();
-				context.insert("page", page);
-				let rendered = content.template_renderer.render("page.html", &context)?;
-				Ok(Some(Either::Left(HttpResponse::Ok().content_type(ContentType::html()).body(rendered))))
+				let rendered = content.render_page(page)?;
+				Ok(Some(Either::Left(self.html_response(&content.server_config, StatusCode::OK, rendered))))
 			}
 			Some(Content::Post(post)) => {
 				log::debug!("Found post content at {}", req.path());
-				let mut context = tera::Context::new();
-				context.insert("post", post);
-				let rendered = content.template_renderer.render("post.html", &context)?;
-				Ok(Some(Either::Left(HttpResponse::Ok().content_type(ContentType::html()).body(rendered))))
+				let rendered = content.render_post(post)?;
+				Ok(Some(Either::Left(self.html_response(&content.server_config, StatusCode::OK, rendered))))
 			}
-			Some(Content::Redirect(url)) => {
+			Some(Content::Redirect(mut url)) => {
 				log::debug!("Found redirect at {}", req.path());
+				// the query string is kept so that things like a preview key still apply after being redirected
+				if !req.query_string().is_empty() {
+					url = format!("{}?{}", url, req.query_string());
+				}
 				Ok(Some(Either::Right(Redirect::to(url).using_status_code(StatusCode::MOVED_PERMANENTLY))))
 			}
 			None => {
@@ -452,3 +1325,219 @@ impl SiteService {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn front_matter_is_split_off() {
+		let raw_content = "---\ntitle: Hello\ntags:\n  - a\n---\nContent\n---\nMore\n";
+		assert_eq!(split_front_matter(raw_content), Some(("title: Hello\ntags:\n  - a\n", "Content\n---\nMore\n")));
+	}
+
+	#[test]
+	fn front_matter_with_crlf_line_endings_is_split_off() {
+		assert_eq!(split_front_matter("---\r\ntitle: Hello\r\n---\r\nContent"), Some(("title: Hello\r\n", "Content")));
+	}
+
+	#[test]
+	fn empty_front_matter_is_split_off() {
+		assert_eq!(split_front_matter("---\n---\nContent"), Some(("", "Content")));
+		assert_eq!(split_front_matter("---\n---"), Some(("", "")));
+	}
+
+	#[test]
+	fn unterminated_front_matter_is_not_front_matter() {
+		assert_eq!(split_front_matter("---\ntitle: Hello\nContent\n"), None);
+	}
+
+	#[test]
+	fn content_without_front_matter_is_left_alone() {
+		assert_eq!(split_front_matter(""), None);
+		assert_eq!(split_front_matter("Content\n---\nMore\n"), None);
+		assert_eq!(split_front_matter("----\ntitle: Hello\n---\n"), None);
+		assert_eq!(split_front_matter(" ---\ntitle: Hello\n---\n"), None);
+	}
+
+	#[test]
+	fn tag_urls_are_percent_encoded() {
+		assert_eq!(tag_url("rust"), "/tag/rust");
+		assert_eq!(tag_url("c#"), "/tag/c%23");
+		assert_eq!(tag_url("node.js"), "/tag/node.js");
+		assert_eq!(tag_url("two words"), "/tag/two%20words");
+		assert_eq!(tag_url("a/b"), "/tag/a%2Fb");
+	}
+
+	fn per_page(per_page: usize) -> Option<NonZeroUsize> {
+		NonZeroUsize::new(per_page)
+	}
+
+	#[test]
+	fn first_page_url_is_the_base_url() {
+		assert_eq!(Paginator::page_url("/archive", 1), "/archive");
+		assert_eq!(Paginator::page_url("/archive", 2), "/archive/page/2");
+	}
+
+	#[test]
+	fn total_pages_is_at_least_one() {
+		assert_eq!(Paginator::total_pages(0, per_page(10)), 1);
+		assert_eq!(Paginator::total_pages(0, None), 1);
+		assert_eq!(Paginator::total_pages(5, None), 1);
+		assert_eq!(Paginator::total_pages(10, per_page(5)), 2);
+		assert_eq!(Paginator::total_pages(11, per_page(5)), 3);
+	}
+
+	#[test]
+	fn items_are_paginated() {
+		let items = [1, 2, 3, 4, 5];
+		let (page, paginator) = Paginator::paginate(&items, 1, per_page(2), "/archive").unwrap();
+		assert_eq!(page, [1, 2]);
+		assert_eq!((paginator.current_page, paginator.total_pages), (1, 3));
+		assert_eq!((paginator.previous_url, paginator.next_url), (None, Some(String::from("/archive/page/2"))));
+
+		let (page, paginator) = Paginator::paginate(&items, 2, per_page(2), "/archive").unwrap();
+		assert_eq!(page, [3, 4]);
+		assert_eq!(
+			(paginator.previous_url, paginator.next_url),
+			(Some(String::from("/archive")), Some(String::from("/archive/page/3")))
+		);
+
+		let (page, paginator) = Paginator::paginate(&items, 3, per_page(2), "/archive").unwrap();
+		assert_eq!(page, [5]);
+		assert_eq!((paginator.previous_url, paginator.next_url), (Some(String::from("/archive/page/2")), None));
+	}
+
+	#[test]
+	fn pages_out_of_range_are_not_paginated() {
+		let items = [1, 2, 3];
+		assert!(Paginator::paginate(&items, 0, per_page(2), "/archive").is_none());
+		assert!(Paginator::paginate(&items, 3, per_page(2), "/archive").is_none());
+		assert!(Paginator::paginate(&items, 2, None, "/archive").is_none());
+	}
+
+	#[test]
+	fn everything_is_on_one_page_without_a_page_size() {
+		let items = [1, 2, 3];
+		let (page, paginator) = Paginator::paginate(&items, 1, None, "/archive").unwrap();
+		assert_eq!(page, [1, 2, 3]);
+		assert_eq!((paginator.total_pages, paginator.previous_url, paginator.next_url), (1, None, None));
+	}
+
+	#[test]
+	fn no_items_is_still_one_page() {
+		let items: [i32; 0] = [];
+		let (page, paginator) = Paginator::paginate(&items, 1, per_page(10), "/archive").unwrap();
+		assert!(page.is_empty());
+		assert_eq!((paginator.total_pages, paginator.next_url), (1, None));
+	}
+
+	fn page(url: &str, alternate_urls: &[&str], file_path: &str) -> Page {
+		Page {
+			url: url.to_string(),
+			title: String::from("Page"),
+			content_html: String::new(),
+			toc: Vec::new(),
+			alternate_urls: alternate_urls.iter().map(|url| url.to_string()).collect(),
+			modified: None,
+			file_path: PathBuf::from(file_path),
+		}
+	}
+
+	fn describe(errors: Vec<SiteError>) -> Vec<String> {
+		errors.iter().map(ToString::to_string).collect()
+	}
+
+	#[test]
+	fn unique_urls_do_not_collide() {
+		let pages = [page("/a", &["/old-a"], "a.md"), page("/b", &["/old-b", "/older-b"], "b.md")];
+		assert!(find_url_collisions(&pages, &[]).is_empty());
+	}
+
+	#[test]
+	fn duplicate_urls_collide_with_the_first_user_of_the_url() {
+		let pages = [page("/a", &[], "a.md"), page("/a", &[], "b.md"), page("/a", &[], "c.md")];
+		assert_eq!(
+			describe(find_url_collisions(&pages, &[])),
+			["Url /a is used by both a.md and b.md", "Url /a is used by both a.md and c.md"]
+		);
+	}
+
+	#[test]
+	fn alternate_urls_collide_with_urls() {
+		let pages = [page("/a", &[], "a.md"), page("/b", &["/a"], "b.md")];
+		assert_eq!(describe(find_url_collisions(&pages, &[])), ["Alternate url /a of b.md is the url of a.md"]);
+	}
+
+	#[test]
+	fn alternate_urls_collide_with_their_own_url() {
+		let pages = [page("/a", &["/a"], "a.md")];
+		assert_eq!(
+			describe(find_url_collisions(&pages, &[])),
+			["Alternate url /a of a.md is its own url, so it would redirect to itself"]
+		);
+	}
+
+	#[test]
+	fn alternate_urls_collide_with_each_other() {
+		let pages = [page("/a", &["/old"], "a.md"), page("/b", &["/old"], "b.md")];
+		assert_eq!(describe(find_url_collisions(&pages, &[])), ["Url /old is used by both a.md and b.md"]);
+	}
+
+	#[test]
+	fn an_alternate_url_listed_twice_by_the_same_page_does_not_collide() {
+		let pages = [page("/a", &["/old", "/old"], "a.md")];
+		assert!(find_url_collisions(&pages, &[]).is_empty());
+	}
+
+	#[test]
+	fn all_url_collisions_are_found() {
+		let pages = [page("/a", &["/a"], "a.md"), page("/a", &["/c"], "b.md"), page("/c", &[], "c.md")];
+		assert_eq!(find_url_collisions(&pages, &[]).len(), 3);
+	}
+
+	fn rss(url: &str) -> config::Rss {
+		config::Rss { title: String::new(), description: String::new(), url: url.to_string(), count: 10 }
+	}
+
+	#[test]
+	fn site_urls_must_be_absolute() {
+		assert_eq!(parse_site_url(&rss("https://example.com/blog/")).unwrap().as_str(), "https://example.com/blog/");
+		assert!(matches!(
+			parse_site_url(&rss("example.com/blog")),
+			Err(SiteError::InvalidSiteUrlError(url, url::ParseError::RelativeUrlWithoutBase)) if url == "example.com/blog"
+		));
+		assert!(parse_site_url(&rss("")).is_err());
+	}
+
+	#[test]
+	fn tag_url_filter_gives_unescaped_tag_urls() {
+		let mut tera = tera::Tera::default();
+		tera.register_filter("tag_url", TagUrlFilter);
+		tera.add_raw_template("tags.html", "{% for tag in tags %}<a href=\"{{ tag | tag_url }}\"></a>{% endfor %}")
+			.unwrap();
+		let mut context = tera::Context::new();
+		context.insert("tags", &["rust", "c#", "a/b", "<b>"]);
+		assert_eq!(
+			tera.render("tags.html", &context).unwrap(),
+			"<a href=\"/tag/rust\"></a><a href=\"/tag/c%23\"></a><a href=\"/tag/a%2Fb\"></a><a href=\"/tag/%3Cb%3E\"></a>"
+		);
+	}
+
+	#[test]
+	fn content_renderers_render_registered_formats() {
+		let path = std::env::temp_dir().join(format!("pbe-test-{}.shout", std::process::id()));
+		std::fs::write(&path, "---\ntitle: Hi\n---\nhello").unwrap();
+		let server_config = config::tests::server_config("content_formats:\n  shout: shouting\n");
+		let content_renderer = ContentRenderer::with_formats(&server_config, |formats| {
+			formats.register("shouting", formats::tests::ShoutingFormat)
+		})
+		.unwrap();
+		let rendered = content_renderer.render(&path, None);
+		std::fs::remove_file(&path).unwrap();
+		let rendered = rendered.unwrap();
+		assert_eq!(rendered.output.html, "HELLO");
+		assert_eq!(rendered.front_matter.title.as_deref(), Some("Hi"));
+		assert!(ContentRenderer::new(&server_config).is_err());
+	}
+}
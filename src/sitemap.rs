@@ -0,0 +1,32 @@
+/// A single url entry in a sitemap.
+pub struct SitemapUrl {
+	/// The absolute url.
+	pub loc: String,
+	/// The date/time the content at this url was last modified, if known.
+	pub lastmod: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+fn escape_xml(s: &str) -> String {
+	s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// Renders the given urls as an XML sitemap, per the format described at https://www.sitemaps.org/protocol.html
+pub fn render(urls: &[SitemapUrl]) -> String {
+	let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+	for url in urls {
+		xml.push_str("\t<url>\n");
+		xml.push_str(&format!("\t\t<loc>{}</loc>\n", escape_xml(&url.loc)));
+		if let Some(lastmod) = url.lastmod {
+			xml.push_str(&format!("\t\t<lastmod>{}</lastmod>\n", lastmod.to_rfc3339()));
+		}
+		xml.push_str("\t</url>\n");
+	}
+	xml.push_str("</urlset>\n");
+	xml
+}
+
+/// Renders a robots.txt which allows everything and points crawlers at the sitemap.
+pub fn render_robots_txt(sitemap_url: &str) -> String {
+	format!("User-agent: *\nAllow: /\n\nSitemap: {sitemap_url}\n")
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Local};
+
+use crate::util::error_chain;
+
+fn serialize_datetime_to_rfc3339<S>(value: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: serde::Serializer,
+{
+	serializer.serialize_str(&value.to_rfc3339())
+}
+
+/// The outcome of the most recent attempt at refreshing the site content.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RefreshStatus {
+	#[serde(serialize_with = "serialize_datetime_to_rfc3339")]
+	pub refreshed_at: DateTime<Local>,
+	pub success: bool,
+	/// The error that caused the refresh to fail, followed by each of the errors that caused it in turn.
+	pub error: Vec<String>,
+	/// When the content that is currently being served was built. If the most recent refresh failed, this is when
+	/// the last successful refresh happened.
+	#[serde(serialize_with = "serialize_datetime_to_rfc3339")]
+	pub content_built_at: DateTime<Local>,
+}
+
+impl RefreshStatus {
+	pub fn succeeded() -> Self {
+		let now = Local::now();
+		RefreshStatus { refreshed_at: now, success: true, error: Vec::new(), content_built_at: now }
+	}
+
+	pub fn failed(previous: &RefreshStatus, error: &dyn std::error::Error) -> Self {
+		RefreshStatus {
+			refreshed_at: Local::now(),
+			success: false,
+			error: error_chain(error),
+			content_built_at: previous.content_built_at,
+		}
+	}
+
+	/// Returns a banner to show at the bottom of pages describing why the most recent refresh failed, if it did.
+	pub fn render_banner(&self) -> Option<String> {
+		if self.success {
+			return None;
+		}
+		let message = format!(
+			"Refreshing the site failed at {}, so content from {} is still being served:\n\n{}",
+			self.refreshed_at.format("%Y-%m-%d %H:%M:%S"),
+			self.content_built_at.format("%Y-%m-%d %H:%M:%S"),
+			self.error.join("\n  caused by: ")
+		);
+		Some(format!(
+			"<pre id=\"__pbe-refresh-status\" style=\"position: fixed; left: 0; right: 0; bottom: 0; z-index: 2147483646; max-height: 40%; overflow: auto; margin: 0; padding: 1em; background: #a00000; color: #ffffff; font: 13px monospace; white-space: pre-wrap;\">{}</pre>\n",
+			tera::escape_html(&message)
+		))
+	}
+}
@@ -1,3 +1,13 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{Offset, TimeZone};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// The characters which are percent-encoded in a single url path segment, which is everything other than the
+/// unreserved characters.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
 fn parse_datetime_from_str(s: &str) -> Result<chrono::NaiveDateTime, chrono::ParseError> {
 	let dt = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S");
 	if dt.is_ok() {
@@ -15,11 +25,11 @@ fn parse_datetime_from_str(s: &str) -> Result<chrono::NaiveDateTime, chrono::Par
 	}
 }
 
-pub fn deserialize_string_to_naivedatetime<'de, D: serde::Deserializer<'de>>(
+pub fn deserialize_optional_string_to_naivedatetime<'de, D: serde::Deserializer<'de>>(
 	deserializer: D,
-) -> Result<chrono::NaiveDateTime, D::Error> {
-	let s: String = serde::Deserialize::deserialize(deserializer)?;
-	parse_datetime_from_str(&s).map_err(serde::de::Error::custom)
+) -> Result<Option<chrono::NaiveDateTime>, D::Error> {
+	let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+	s.map(|s| parse_datetime_from_str(&s).map_err(serde::de::Error::custom)).transpose()
 }
 
 pub fn serialize_naivedatetime_to_i64<S: serde::Serializer>(
@@ -29,8 +39,438 @@ pub fn serialize_naivedatetime_to_i64<S: serde::Serializer>(
 	serializer.serialize_i64(value.timestamp())
 }
 
+/// Converts a date/time in the given time zone, or the local system time zone if none is given, to a date/time with
+/// a fixed offset from utc.
+pub fn localize_datetime(
+	date: &chrono::NaiveDateTime,
+	timezone: Option<&chrono_tz::Tz>,
+) -> chrono::DateTime<chrono::FixedOffset> {
+	fn with_fixed_offset<Tz: TimeZone>(date: chrono::DateTime<Tz>) -> chrono::DateTime<chrono::FixedOffset> {
+		date.with_timezone(&date.offset().fix())
+	}
+	let localized = match timezone {
+		Some(timezone) => timezone.from_local_datetime(date).earliest().map(with_fixed_offset),
+		None => chrono::Local.from_local_datetime(date).earliest().map(with_fixed_offset),
+	};
+	// a date/time that falls within a daylight-saving time gap does not exist, so fall back to treating it as utc
+	localized.unwrap_or_else(|| with_fixed_offset(chrono::Utc.from_utc_datetime(date)))
+}
+
+/// Returns the current date/time in the given time zone, or the local system time zone if none is given.
+pub fn current_datetime(timezone: Option<&chrono_tz::Tz>) -> chrono::NaiveDateTime {
+	match timezone {
+		Some(timezone) => chrono::Utc::now().with_timezone(timezone).naive_local(),
+		None => chrono::Local::now().naive_local(),
+	}
+}
+
+/// Returns the messages of an error and of the chain of errors that caused it, starting with the given error.
+pub fn error_chain(error: &dyn std::error::Error) -> Vec<String> {
+	let mut messages = vec![error.to_string().trim().to_string()];
+	let mut source = error.source();
+	while let Some(error) = source {
+		messages.push(error.to_string().trim().to_string());
+		source = error.source();
+	}
+	messages
+}
+
+/// Describes an error along with the chain of errors that caused it, one per line.
+pub fn describe_error_chain(error: &dyn std::error::Error) -> String {
+	error_chain(error).join("\n  caused by: ")
+}
+
+/// Inserts the given snippet into an HTML document, just before the closing `</body>` tag if there is one.
+pub fn insert_before_body_end(html: &mut String, snippet: &str) {
+	let position = html.rfind("</body>").unwrap_or(html.len());
+	html.insert_str(position, snippet);
+}
+
+/// Percent-encodes the given text for use as a single segment of a url path, e.g. `c#` becomes `c%23`.
+pub fn encode_path_segment(s: &str) -> String {
+	utf8_percent_encode(s, PATH_SEGMENT).to_string()
+}
+
+/// Decodes any percent-encoded characters in a url path, e.g. `/tag/c%23` becomes `/tag/c#`.
+pub fn decode_path(s: &str) -> String {
+	percent_decode_str(s).decode_utf8_lossy().to_string()
+}
+
+/// Locks a cache, even if it was poisoned by a panic while it was locked. Caches only ever have complete entries
+/// inserted into them in a single step, so a panic can never leave one half-updated, and it is fine to keep using it.
+pub fn lock_cache<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+	cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 pub fn drop_trailing_slash(s: &mut String) {
 	if s.ends_with("/") {
 		s.pop();
 	}
 }
+
+pub fn copy_dir_all(source: &Path, destination: &Path) -> std::io::Result<()> {
+	std::fs::create_dir_all(destination)?;
+	for entry in std::fs::read_dir(source)? {
+		let entry = entry?;
+		let destination = destination.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir_all(&entry.path(), &destination)?;
+		} else {
+			std::fs::copy(entry.path(), destination)?;
+		}
+	}
+	Ok(())
+}
+
+/// Recursively finds all files under the given path, sorted by path. Hidden files and editor backup files are skipped.
+pub fn find_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	for entry in std::fs::read_dir(path)? {
+		let entry = entry?;
+		let file_name = entry.file_name().to_string_lossy().to_string();
+		if file_name.starts_with('.') || file_name.ends_with('~') {
+			continue;
+		}
+		if entry.file_type()?.is_dir() {
+			files.extend(find_files(&entry.path())?);
+		} else {
+			files.push(entry.path());
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+/// Parses the date and slug out of a post file path whose file name is in the format `YYYY-MM-DD-slug.ext`.
+pub fn parse_post_file_path(path: &Path) -> Option<(chrono::NaiveDateTime, String)> {
+	let file_stem = path.file_stem()?.to_str()?;
+	let (date, slug) = (file_stem.get(..10)?, file_stem.get(10..)?.strip_prefix('-')?);
+	let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+	if slug.is_empty() {
+		None
+	} else {
+		Some((date.and_time(chrono::NaiveTime::default()), slug.to_string()))
+	}
+}
+
+/// Derives a url for a page from its file path relative to the pages path, e.g. `pages/about/me.md` becomes
+/// `/about/me`.
+pub fn url_from_page_file_path(file_path: &Path, pages_path: &Path) -> Option<String> {
+	let relative_path = file_path.strip_prefix(pages_path).ok()?.with_extension("");
+	let parts = relative_path.iter().map(|part| part.to_str()).collect::<Option<Vec<&str>>>()?;
+	Some(format!("/{}", parts.join("/")))
+}
+
+/// Converts HTML to plain text by stripping out all tags and comments, decoding the most common character entities
+/// and collapsing all whitespace. Block-level tags are treated as whitespace, inline tags are removed entirely.
+pub fn html_to_text(html: &str) -> String {
+	const BLOCK_TAGS: &[&str] = &[
+		"address",
+		"blockquote",
+		"br",
+		"dd",
+		"div",
+		"dl",
+		"dt",
+		"figcaption",
+		"figure",
+		"h1",
+		"h2",
+		"h3",
+		"h4",
+		"h5",
+		"h6",
+		"hr",
+		"li",
+		"ol",
+		"p",
+		"pre",
+		"section",
+		"table",
+		"td",
+		"th",
+		"tr",
+		"ul",
+	];
+
+	let mut text = String::with_capacity(html.len());
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		text.push_str(&rest[..start]);
+		let tag = &rest[start..];
+		let end = if tag.starts_with("<!--") { tag.find("-->").map(|i| i + 3) } else { tag.find('>').map(|i| i + 1) };
+		let tag_name = tag[1..]
+			.trim_start_matches('/')
+			.split(|c: char| !c.is_ascii_alphanumeric())
+			.next()
+			.unwrap_or_default()
+			.to_ascii_lowercase();
+		if BLOCK_TAGS.contains(&tag_name.as_str()) {
+			text.push(' ');
+		}
+		rest = end.map_or("", |end| &tag[end..]);
+	}
+	text.push_str(rest);
+	decode_entities(&text).split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Decodes the most common named character entities, and all numeric ones (both decimal, e.g. `&#8217;`, and hex,
+/// e.g. `&#x2014;`). Anything that is not a valid entity is left as-is.
+fn decode_entities(text: &str) -> String {
+	const NAMED_ENTITIES: [(&str, char); 6] =
+		[("lt", '<'), ("gt", '>'), ("quot", '"'), ("apos", '\''), ("nbsp", ' '), ("amp", '&')];
+
+	let mut decoded = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('&') {
+		decoded.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+		let c = entity.and_then(|entity| match entity.strip_prefix('#') {
+			Some(number) => {
+				let (digits, radix) = match number.strip_prefix(['x', 'X']) {
+					Some(hex) => (hex, 16),
+					None => (number, 10),
+				};
+				// from_str_radix would also accept a leading sign, which is not valid here
+				let is_valid = digits.chars().all(|c| c.is_digit(radix));
+				is_valid.then(|| u32::from_str_radix(digits, radix).ok()).flatten().and_then(char::from_u32)
+			}
+			None => NAMED_ENTITIES.iter().find(|(name, _)| *name == entity).map(|(_, c)| *c),
+		});
+		match (entity, c) {
+			(Some(entity), Some(c)) => {
+				decoded.push(c);
+				rest = &rest[entity.len() + 2..];
+			}
+			_ => {
+				decoded.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	decoded.push_str(rest);
+	decoded
+}
+
+/// Truncates the given text to at most `max_length` characters, ending on a word boundary with an ellipsis if the
+/// text needed to be truncated.
+pub fn truncate_text(text: &str, max_length: usize) -> String {
+	if text.chars().count() <= max_length {
+		return text.to_string();
+	}
+	let mut truncated = String::new();
+	for word in text.split(' ') {
+		if truncated.chars().count() + word.chars().count() + 1 > max_length.saturating_sub(3) {
+			break;
+		}
+		if !truncated.is_empty() {
+			truncated.push(' ');
+		}
+		truncated.push_str(word);
+	}
+	truncated.push_str("...");
+	truncated
+}
+
+/// Returns the values of all of the `href` and `src` attributes of the tags in the given HTML, in the order they
+/// appear. Only quoted attribute values are returned, and `&amp;` entities in them are decoded.
+pub fn find_link_urls(html: &str) -> Vec<String> {
+	let mut urls = Vec::new();
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		let tag = &rest[start..];
+		if tag.starts_with("<!--") {
+			rest = tag.find("-->").map_or("", |end| &tag[end + 3..]);
+			continue;
+		}
+		let end = tag.find('>').unwrap_or(tag.len());
+		let mut attributes = &tag[..end];
+		while let Some(position) = attributes.find(|c: char| c.is_ascii_whitespace()) {
+			attributes = attributes[position..].trim_start();
+			let name_end = attributes.find(|c: char| c == '=' || c.is_ascii_whitespace()).unwrap_or(attributes.len());
+			let name = &attributes[..name_end];
+			let value = attributes[name_end..].trim_start().strip_prefix('=').map(str::trim_start);
+			let Some(value) = value else {
+				continue;
+			};
+			let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+				attributes = value;
+				continue;
+			};
+			let value_end = value[1..].find(quote).map_or(value.len(), |i| i + 1);
+			if name.eq_ignore_ascii_case("href") || name.eq_ignore_ascii_case("src") {
+				urls.push(value[1..value_end].replace("&amp;", "&"));
+			}
+			attributes = value.get(value_end + 1..).unwrap_or_default();
+		}
+		rest = tag.get(end + 1..).unwrap_or_default();
+	}
+	urls
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(year: i32, month: u32, day: u32) -> chrono::NaiveDateTime {
+		chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap().and_time(chrono::NaiveTime::default())
+	}
+
+	#[test]
+	fn post_file_paths_are_parsed() {
+		assert_eq!(
+			parse_post_file_path(Path::new("posts/2023-07-04-new-post.md")),
+			Some((date(2023, 7, 4), String::from("new-post")))
+		);
+		assert_eq!(
+			parse_post_file_path(Path::new("2023-07-04-no-extension")),
+			Some((date(2023, 7, 4), String::from("no-extension")))
+		);
+		assert_eq!(
+			parse_post_file_path(Path::new("posts/2024/2024-02-29-leap.html")),
+			Some((date(2024, 2, 29), String::from("leap")))
+		);
+	}
+
+	#[test]
+	fn post_file_paths_with_bad_dates_are_not_parsed() {
+		assert_eq!(parse_post_file_path(Path::new("2023-02-30-no-such-day.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("2023-13-01-no-such-month.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("23-07-04-short-year.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("new-post.md")), None);
+	}
+
+	#[test]
+	fn post_file_paths_without_slugs_are_not_parsed() {
+		assert_eq!(parse_post_file_path(Path::new("2023-07-04.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("2023-07-04-.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("2023-07-04_slug.md")), None);
+	}
+
+	#[test]
+	fn post_file_paths_with_multi_byte_characters_are_not_parsed() {
+		// slicing at a byte offset in the middle of a character must not panic
+		assert_eq!(parse_post_file_path(Path::new("2023-07-0é-slug.md")), None);
+		assert_eq!(parse_post_file_path(Path::new("éé.md")), None);
+	}
+
+	#[test]
+	fn page_urls_are_derived_from_file_paths() {
+		let pages_path = Path::new("/site/pages");
+		assert_eq!(
+			url_from_page_file_path(Path::new("/site/pages/about.md"), pages_path),
+			Some(String::from("/about"))
+		);
+		assert_eq!(
+			url_from_page_file_path(Path::new("/site/pages/misc/colophon.html"), pages_path),
+			Some(String::from("/misc/colophon"))
+		);
+		assert_eq!(
+			url_from_page_file_path(Path::new("/site/pages/no-extension"), pages_path),
+			Some(String::from("/no-extension"))
+		);
+	}
+
+	#[test]
+	fn page_urls_are_not_derived_from_file_paths_outside_of_the_pages_path() {
+		assert_eq!(url_from_page_file_path(Path::new("/site/posts/about.md"), Path::new("/site/pages")), None);
+	}
+
+	#[test]
+	fn html_is_converted_to_text() {
+		assert_eq!(html_to_text("<p>Hello, <em>world</em>!</p><p>Second</p>"), "Hello, world! Second");
+		assert_eq!(html_to_text("<ul><li>one</li><li>two</li></ul>"), "one two");
+		assert_eq!(html_to_text("before<!-- <p>comment</p> -->after"), "beforeafter");
+		assert_eq!(html_to_text("  lots \n\t of   space  "), "lots of space");
+		assert_eq!(html_to_text(""), "");
+	}
+
+	#[test]
+	fn unterminated_tags_and_comments_are_dropped() {
+		assert_eq!(html_to_text("text <p unterminated"), "text");
+		assert_eq!(html_to_text("text <!-- unterminated"), "text");
+	}
+
+	#[test]
+	fn named_entities_are_decoded() {
+		assert_eq!(html_to_text("&lt;tag&gt; &quot;quoted&quot; it&apos;s&nbsp;here"), "<tag> \"quoted\" it's here");
+		// only decoded once, so an escaped entity is left as an entity
+		assert_eq!(html_to_text("&amp;lt; &amp;amp;"), "&lt; &amp;");
+	}
+
+	#[test]
+	fn numeric_entities_are_decoded() {
+		assert_eq!(html_to_text("it&#39;s"), "it's");
+		assert_eq!(html_to_text("it&#8217;s"), "it\u{2019}s");
+		assert_eq!(html_to_text("a&#x2014;b&#X2014;c"), "a\u{2014}b\u{2014}c");
+	}
+
+	#[test]
+	fn invalid_entities_are_left_alone() {
+		assert_eq!(html_to_text("fish & chips"), "fish & chips");
+		assert_eq!(
+			html_to_text("&unknown; &#; &#x; &#+65; &#xZZ; &#55296; &#99999999999;"),
+			"&unknown; &#; &#x; &#+65; &#xZZ; &#55296; &#99999999999;"
+		);
+		assert_eq!(html_to_text("trailing &amp"), "trailing &amp");
+	}
+
+	#[test]
+	fn short_text_is_not_truncated() {
+		assert_eq!(truncate_text("", 10), "");
+		assert_eq!(truncate_text("exactly 10", 10), "exactly 10");
+	}
+
+	#[test]
+	fn long_text_is_truncated_on_a_word_boundary() {
+		assert_eq!(truncate_text("the quick brown fox jumps", 15), "the quick...");
+		assert_eq!(truncate_text("the quick brown fox jumps", 20), "the quick brown...");
+	}
+
+	#[test]
+	fn truncation_counts_characters_rather_than_bytes() {
+		assert_eq!(truncate_text("\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}", 5), "\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}");
+		assert_eq!(truncate_text("\u{e9}\u{e9} \u{e9}\u{e9} \u{e9}\u{e9}", 7), "\u{e9}\u{e9}...");
+	}
+
+	#[test]
+	fn a_single_long_word_is_truncated_to_just_an_ellipsis() {
+		assert_eq!(truncate_text("supercalifragilistic", 10), "...");
+	}
+
+	#[test]
+	fn link_urls_are_found() {
+		assert_eq!(
+			find_link_urls(
+				"<a href=\"/one\">one</a> <img alt=\"x\" src='/two.png'> <link rel=\"stylesheet\" href=\"/three.css\">"
+			),
+			["/one", "/two.png", "/three.css"]
+		);
+		assert_eq!(find_link_urls("<A HREF=\"/upper\">"), ["/upper"]);
+		assert_eq!(find_link_urls("<a href = \"/spaced\" >"), ["/spaced"]);
+		assert_eq!(find_link_urls("<a href=\"/a?x=1&amp;y=2\">"), ["/a?x=1&y=2"]);
+		assert!(find_link_urls("").is_empty());
+	}
+
+	#[test]
+	fn only_quoted_href_and_src_attributes_are_link_urls() {
+		assert!(find_link_urls("<a href=/unquoted>").is_empty());
+		assert!(find_link_urls("<a data-href=\"/data\" title=\"href='/title'\">").is_empty());
+		assert!(find_link_urls("<p>href=\"/text\"</p>").is_empty());
+		assert_eq!(find_link_urls("<input disabled href=\"/after-boolean\">"), ["/after-boolean"]);
+	}
+
+	#[test]
+	fn link_urls_in_comments_are_skipped() {
+		assert_eq!(find_link_urls("<!-- <a href=\"/commented\"> --><a href=\"/real\">"), ["/real"]);
+		assert!(find_link_urls("<!-- <a href=\"/unterminated\">").is_empty());
+	}
+
+	#[test]
+	fn unterminated_tags_and_values_do_not_panic() {
+		assert_eq!(find_link_urls("<a href=\"/unterminated"), ["/unterminated"]);
+		assert!(find_link_urls("<a href=").is_empty());
+		assert!(find_link_urls("<").is_empty());
+	}
+}
@@ -1,7 +1,8 @@
 use std::path::Path;
 use std::time::Duration;
 
-use async_watcher::{notify::RecursiveMode, AsyncDebouncer, DebouncedEvent};
+pub use async_watcher::DebouncedEvent;
+use async_watcher::{notify::RecursiveMode, AsyncDebouncer};
 use tokio::sync::mpsc::channel;
 
 #[derive(Debug, thiserror::Error)]